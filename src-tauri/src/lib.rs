@@ -8,11 +8,22 @@ mod utils;
 
 use crate::build_app::build;
 use crate::live::bptimer_state::create_bptimer_enabled;
-use crate::live::opcodes_models::EncounterMutex;
+use crate::live::encounter_history::{
+    EncounterHistory, EncounterHistoryMutex, ResetSnapshotsMutex,
+};
+use crate::live::live_main::EncounterControl;
+use crate::live::live_updates::{LIVE_UPDATE_CAPACITY, LiveUpdate};
+use crate::live::local_api::{LocalApiConfig, LocalApiServerMutex};
+use crate::live::meter_settings::{
+    FightStartTrigger, MeterSettings, MeterSettingsMutex, TargetFilter,
+};
+use crate::live::opcodes_models::Encounter;
 use crate::live::player_state::{PlayerCacheMutex, PlayerStateMutex};
+use crate::live::webhook::{WebhookConfig, WebhookConfigMutex, WebhookFormat};
 use chrono::Utc;
 use log::{info, warn};
 use std::fs;
+use std::sync::Arc;
 
 use tauri::Emitter;
 use tauri::menu::MenuBuilder;
@@ -21,7 +32,8 @@ use tauri::{LogicalPosition, LogicalSize, Manager, Position, Size, Window, Windo
 use tauri_plugin_log::fern::colors::ColoredLevelConfig;
 use tauri_plugin_svelte::ManagerExt;
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
-use tauri_specta::{Builder, collect_commands};
+use tauri_specta::{Builder, collect_commands, collect_events};
+use tokio::sync::{broadcast, mpsc, watch};
 
 pub const WINDOW_LIVE_LABEL: &str = "live";
 
@@ -53,14 +65,44 @@ pub fn run() {
             live::commands::get_dps_boss_only_skill_window,
             live::commands::get_heal_player_window,
             live::commands::get_heal_skill_window,
+            live::commands::get_dps_filtered_player_window,
+            live::commands::get_dps_filtered_skill_window,
+            live::commands::get_dps_timeline_window,
+            live::commands::get_dps_boss_only_timeline_window,
+            live::commands::get_heal_timeline_window,
+            live::commands::get_dps_filtered_timeline_window,
+            live::commands::query_encounter,
             live::commands::reset_encounter,
+            live::commands::get_reset_snapshots,
+            live::commands::restore_encounter,
             live::commands::toggle_pause_encounter,
             live::commands::hard_reset,
             live::commands::quit_app,
             live::commands::get_test_player_window,
             live::commands::get_test_skill_window,
             live::commands::set_bptimer_enabled,
+            live::commands::set_rolling_window_secs,
+            live::commands::set_live_update_interval_ms,
+            live::commands::set_local_api_config,
+            live::commands::set_webhook_config,
+            live::commands::set_encounter_idle_timeout_secs,
+            live::commands::set_fight_start_trigger,
+            live::commands::set_target_filters,
+            live::commands::get_encounter_history,
+            live::commands::load_encounter,
+            live::commands::rename_encounter,
+            live::commands::delete_encounter,
+            live::commands::export_encounter,
+            live::commands::export_encounter_csv,
+            live::commands::encode_share_code,
+            live::commands::decode_share_code,
             live::commands::extract_modules_from_local_player,
+        ])
+        .events(collect_events![
+            live::live_updates::HeaderInfoUpdated,
+            live::live_updates::DpsPlayersUpdated,
+            live::live_updates::DpsBossOnlyPlayersUpdated,
+            live::live_updates::HealPlayersUpdated,
         ]);
 
     #[cfg(debug_assertions)] // <- Only export on non-release builds
@@ -87,8 +129,9 @@ pub fn run() {
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .invoke_handler(builder.invoke_handler())
-        .setup(|app| {
+        .setup(move |app| {
             info!("starting app v{}", app.package_info().version);
+            builder.mount_events(app);
             // Clean up any leftover WinDivert resources from previous runs
             #[cfg(target_os = "windows")]
             service::stop_windivert();
@@ -115,12 +158,98 @@ pub fn run() {
             // https://v2.tauri.app/learn/splashscreen/#start-some-setup-tasks
             let is_bptimer_enabled = app.svelte().get_or::<bool>("integration", "bptimer", true);
             app.manage(create_bptimer_enabled(is_bptimer_enabled)); // setup bptimer enabled state
-            app.manage(EncounterMutex::default()); // setup encounter state
+            let mut meter_settings = MeterSettings::default();
+            meter_settings.set_rolling_window_secs(app.svelte().get_or::<u32>(
+                "general",
+                "rollingWindowSecs",
+                meter_settings.rolling_window_secs,
+            ));
+            meter_settings.set_live_update_interval_ms(app.svelte().get_or::<u32>(
+                "general",
+                "liveUpdateIntervalMs",
+                meter_settings.live_update_interval_ms,
+            ));
+            meter_settings.encounter_idle_timeout_secs = app.svelte().get_or::<u32>(
+                "general",
+                "resetElapsed",
+                meter_settings.encounter_idle_timeout_secs,
+            );
+            meter_settings.fight_start_trigger = app.svelte().get_or::<FightStartTrigger>(
+                "general",
+                "fightStartTrigger",
+                meter_settings.fight_start_trigger,
+            );
+            meter_settings.target_filters = app
+                .svelte()
+                .get_or::<Vec<TargetFilter>>("general", "targetFilters", Vec::new())
+                .into();
+            app.manage(MeterSettingsMutex::new(meter_settings)); // setup meter settings
+            let webhook_config = WebhookConfig {
+                enabled: app.svelte().get_or::<bool>("integration", "webhook", false),
+                url: app
+                    .svelte()
+                    .get_or::<String>("integration", "webhookUrl", String::new()),
+                format: app.svelte().get_or::<WebhookFormat>(
+                    "integration",
+                    "webhookFormat",
+                    WebhookFormat::default(),
+                ),
+            };
+            app.manage(WebhookConfigMutex::new(webhook_config)); // setup encounter webhook
+            let (encounter_publisher, encounter_snapshot) =
+                watch::channel(Arc::new(Encounter::default()));
+            app.manage(encounter_snapshot); // setup encounter snapshots
+            let (encounter_control_sender, encounter_control_receiver) =
+                mpsc::unbounded_channel::<EncounterControl>();
+            app.manage(encounter_control_sender); // setup encounter controls
+            let encounters_dir = app
+                .path()
+                .app_data_dir()
+                .ok()
+                .map(|dir| dir.join("encounters"));
+            app.manage(EncounterHistoryMutex::new(EncounterHistory::new(
+                encounters_dir,
+            ))); // setup encounter history
+            app.manage(ResetSnapshotsMutex::default()); // setup reset undo
             app.manage(PlayerStateMutex::default()); // setup player state
             app.manage(PlayerCacheMutex::default()); // setup player cache
-            tauri::async_runtime::spawn(
-                async move { live::live_main::start(app_handle.clone()).await },
-            );
+            let (live_update_sender, _) = broadcast::channel::<LiveUpdate>(LIVE_UPDATE_CAPACITY);
+            app.manage(live_update_sender); // setup live updates outside the webview
+            app.manage(LocalApiServerMutex::default()); // setup local api
+            let default_local_api_config = LocalApiConfig::default();
+            let local_api_config = LocalApiConfig {
+                enabled: app.svelte().get_or::<bool>(
+                    "integration",
+                    "localApi",
+                    default_local_api_config.enabled,
+                ),
+                port: app.svelte().get_or::<u16>(
+                    "integration",
+                    "localApiPort",
+                    default_local_api_config.port,
+                ),
+                token: app.svelte().get_or::<String>(
+                    "integration",
+                    "localApiToken",
+                    default_local_api_config.token,
+                ),
+            };
+            let local_api_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                live::local_api::restart(&local_api_app_handle, local_api_config).await
+            });
+            let live_updates_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                live::live_updates::start(live_updates_app_handle).await
+            });
+            tauri::async_runtime::spawn(async move {
+                live::live_main::start(
+                    app_handle.clone(),
+                    encounter_publisher,
+                    encounter_control_receiver,
+                )
+                .await
+            });
             Ok(())
         })
         .on_window_event(on_window_event_fn)
@@ -2,9 +2,19 @@
 // Preferred way is to name modules with their subfolder name now (no longer mod.rs)
 pub mod bptimer;
 pub mod bptimer_state;
+pub mod combat_log;
 pub mod commands;
 mod commands_models;
+mod encounter_export;
+pub mod encounter_history;
+mod encounter_query;
 pub mod live_main;
+pub mod live_updates;
+pub mod local_api;
+pub mod meter_settings;
+mod metrics;
 pub mod opcodes_models;
 mod opcodes_process;
 pub mod player_state;
+mod share_code;
+pub mod webhook;
@@ -0,0 +1,468 @@
+use crate::live::meter_settings::TargetFilter;
+use crate::live::opcodes_models::{Encounter, MONSTER_NAMES_BOSS};
+use std::sync::Arc;
+
+// Bits of CombatLog::flags
+pub const FLAG_HEAL: u8 = 1 << 0;
+pub const FLAG_CRIT: u8 = 1 << 1;
+pub const FLAG_DEAD: u8 = 1 << 2;
+
+/// A single damage or heal event
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CombatEvent {
+    pub timestamp_ms: u128,
+    pub attacker_uid: i64, // owner of the summon for summon damage
+    pub summon_uid: i64,   // the summon itself for summon damage, 0 otherwise
+    pub target_uid: i64,
+    pub skill_uid: i32,
+    pub value: i64,
+    pub lucky_value: i64,
+    pub hp_lessened: i64,
+    pub is_heal: bool,
+    pub is_crit: bool,
+    pub is_dead: bool, // the target died from this hit
+}
+
+impl CombatEvent {
+    pub fn is_summon(&self) -> bool {
+        self.summon_uid != 0
+    }
+
+    pub fn is_lucky(&self) -> bool {
+        self.lucky_value != 0
+    }
+
+    /// Value counted towards the stats, lucky hits only count their lucky value
+    pub fn actual_value(&self) -> i64 {
+        if self.is_lucky() {
+            self.lucky_value
+        } else {
+            self.value
+        }
+    }
+}
+
+// Events per chunk of the log, full chunks are shared between encounter snapshots instead of copied
+pub const COMBAT_LOG_CHUNK_LEN: usize = 4096;
+
+/// Damage and heal events stored one column per field
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CombatLogColumns {
+    pub timestamps_ms: Vec<u64>,
+    pub attacker_uids: Vec<i64>,
+    pub summon_uids: Vec<i64>,
+    pub target_uids: Vec<i64>,
+    pub skill_uids: Vec<i32>,
+    pub values: Vec<i64>,
+    pub lucky_values: Vec<i64>,
+    pub hp_lessened: Vec<i64>,
+    pub flags: Vec<u8>,
+}
+
+impl CombatLogColumns {
+    pub fn push(&mut self, combat_event: &CombatEvent) {
+        let mut flags = 0;
+        if combat_event.is_heal {
+            flags |= FLAG_HEAL;
+        }
+        if combat_event.is_crit {
+            flags |= FLAG_CRIT;
+        }
+        if combat_event.is_dead {
+            flags |= FLAG_DEAD;
+        }
+        self.timestamps_ms.push(combat_event.timestamp_ms as u64);
+        self.attacker_uids.push(combat_event.attacker_uid);
+        self.summon_uids.push(combat_event.summon_uid);
+        self.target_uids.push(combat_event.target_uid);
+        self.skill_uids.push(combat_event.skill_uid);
+        self.values.push(combat_event.value);
+        self.lucky_values.push(combat_event.lucky_value);
+        self.hp_lessened.push(combat_event.hp_lessened);
+        self.flags.push(flags);
+    }
+
+    fn extend(&mut self, other: &CombatLogColumns) {
+        self.timestamps_ms.extend(&other.timestamps_ms);
+        self.attacker_uids.extend(&other.attacker_uids);
+        self.summon_uids.extend(&other.summon_uids);
+        self.target_uids.extend(&other.target_uids);
+        self.skill_uids.extend(&other.skill_uids);
+        self.values.extend(&other.values);
+        self.lucky_values.extend(&other.lucky_values);
+        self.hp_lessened.extend(&other.hp_lessened);
+        self.flags.extend(&other.flags);
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps_ms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps_ms.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<CombatEvent> {
+        let flags = *self.flags.get(index)?;
+        Some(CombatEvent {
+            timestamp_ms: u128::from(self.timestamps_ms[index]),
+            attacker_uid: self.attacker_uids[index],
+            summon_uid: self.summon_uids[index],
+            target_uid: self.target_uids[index],
+            skill_uid: self.skill_uids[index],
+            value: self.values[index],
+            lucky_value: self.lucky_values[index],
+            hp_lessened: self.hp_lessened[index],
+            is_heal: flags & FLAG_HEAL != 0,
+            is_crit: flags & FLAG_CRIT != 0,
+            is_dead: flags & FLAG_DEAD != 0,
+        })
+    }
+}
+
+/// Every counted damage and heal event of an encounter in processing order.
+/// All stats of an encounter can be rebuilt from it, see `project`.
+/// Timestamps are on the fight clock, so time spent paused is cut out (see `Encounter::resume`).
+/// Serialized as a single `CombatLogColumns`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "CombatLogColumns", into = "CombatLogColumns")]
+pub struct CombatLog {
+    full_chunks: Vec<Arc<CombatLogColumns>>, // each COMBAT_LOG_CHUNK_LEN events long
+    last_chunk: CombatLogColumns,
+}
+
+impl CombatLog {
+    pub fn push(&mut self, combat_event: &CombatEvent) {
+        self.last_chunk.push(combat_event);
+        if self.last_chunk.len() == COMBAT_LOG_CHUNK_LEN {
+            let full_chunk = std::mem::take(&mut self.last_chunk);
+            self.full_chunks.push(Arc::new(full_chunk));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.full_chunks.len() * COMBAT_LOG_CHUNK_LEN + self.last_chunk.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<CombatEvent> {
+        match self.full_chunks.get(index / COMBAT_LOG_CHUNK_LEN) {
+            Some(chunk) => chunk.get(index % COMBAT_LOG_CHUNK_LEN),
+            None => self
+                .last_chunk
+                .get(index - self.full_chunks.len() * COMBAT_LOG_CHUNK_LEN),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CombatEvent> + '_ {
+        self.full_chunks
+            .iter()
+            .map(|chunk| &**chunk)
+            .chain(std::iter::once(&self.last_chunk))
+            .flat_map(|chunk| (0..chunk.len()).filter_map(|index| chunk.get(index)))
+    }
+
+    /// Moves every event later by `ms`, used to cut a pause out of the fight clock
+    pub fn shift(&mut self, ms: u64) {
+        let chunks = self
+            .full_chunks
+            .iter_mut()
+            .map(Arc::make_mut)
+            .chain(std::iter::once(&mut self.last_chunk));
+        for chunk in chunks {
+            for timestamp_ms in &mut chunk.timestamps_ms {
+                *timestamp_ms += ms;
+            }
+        }
+    }
+}
+
+impl From<CombatLogColumns> for CombatLog {
+    fn from(columns: CombatLogColumns) -> Self {
+        let mut combat_log = CombatLog::default();
+        for index in 0..columns.len() {
+            if let Some(combat_event) = columns.get(index) {
+                combat_log.push(&combat_event);
+            }
+        }
+        combat_log
+    }
+}
+
+impl From<CombatLog> for CombatLogColumns {
+    fn from(combat_log: CombatLog) -> Self {
+        let mut columns = CombatLogColumns::default();
+        for chunk in &combat_log.full_chunks {
+            columns.extend(chunk);
+        }
+        columns.extend(&combat_log.last_chunk);
+        columns
+    }
+}
+
+/// Logs a damage or heal event and adds it to the stats of `encounter`.
+/// Targets are looked up in `encounter.entity_uid_to_entity` to decide boss and filter stats.
+pub fn record(
+    encounter: &mut Encounter,
+    combat_event: &CombatEvent,
+    target_filters: &[TargetFilter],
+) {
+    let target_entity = encounter.entity_uid_to_entity.get(&combat_event.target_uid);
+    let target_monster_id = target_entity.and_then(|e| e.monster_id);
+    let target_max_hp = target_entity.and_then(|e| e.max_hp);
+    let is_boss = target_monster_id.is_some_and(|id| MONSTER_NAMES_BOSS.contains_key(&id));
+    let filter_ids = target_filters
+        .iter()
+        .filter(|target_filter| target_filter.matches(target_monster_id, target_max_hp))
+        .map(|target_filter| target_filter.id)
+        .collect::<Vec<_>>();
+    apply_combat_event(encounter, combat_event, is_boss, &filter_ids);
+    encounter.combat_log.push(combat_event);
+}
+
+/// Records the events of `combat_log` that `select` keeps into `encounter`, which is how every
+/// stat window can be rebuilt for other filters or time ranges. `select` may also change an
+/// event before it's recorded, e.g. to credit it to someone else.
+pub fn project(
+    encounter: &mut Encounter,
+    combat_log: &CombatLog,
+    target_filters: &[TargetFilter],
+    select: impl FnMut(CombatEvent) -> Option<CombatEvent>,
+) {
+    for combat_event in combat_log.iter().filter_map(select) {
+        record(encounter, &combat_event, target_filters);
+    }
+}
+
+/// Rebuilds all stats of `encounter` from its log, e.g. after the target filters changed
+pub fn recompute_encounter(encounter: &mut Encounter, target_filters: &[TargetFilter]) {
+    let mut recomputed = encounter.without_stats();
+    recomputed.time_fight_start_ms = encounter.time_fight_start_ms;
+    recomputed.is_boss_defeated = encounter.is_boss_defeated;
+    recomputed.line_id = encounter.line_id;
+    recomputed.level_map_id = encounter.level_map_id;
+    project(&mut recomputed, &encounter.combat_log, target_filters, Some);
+    *encounter = recomputed;
+}
+
+/// Adds a damage or heal event to the attacker and encounter stats it counts towards.
+/// `filter_ids` are the target filters the event's target matches.
+fn apply_combat_event(
+    encounter: &mut Encounter,
+    combat_event: &CombatEvent,
+    is_boss: bool,
+    filter_ids: &[u32],
+) {
+    let CombatEvent {
+        timestamp_ms,
+        skill_uid,
+        is_crit,
+        ..
+    } = *combat_event;
+    let value = combat_event.actual_value();
+    let is_lucky = combat_event.is_lucky();
+    // Packets are processed concurrently, so they can arrive slightly out of capture order
+    encounter.time_last_combat_packet_ms = encounter.time_last_combat_packet_ms.max(timestamp_ms);
+    if !combat_event.is_heal {
+        encounter.time_last_dmg_ms = encounter.time_last_dmg_ms.max(timestamp_ms);
+    }
+    let offset_ms = timestamp_ms.saturating_sub(encounter.time_fight_start_ms);
+    let target_entity = Arc::make_mut(
+        encounter
+            .entity_uid_to_entity
+            .entry(combat_event.target_uid)
+            .or_default(),
+    );
+    target_entity.is_in_combat = true;
+    if !combat_event.is_heal {
+        target_entity.dmg_taken += value;
+        if combat_event.is_dead {
+            target_entity.deaths += 1;
+        }
+    }
+    let attacker_entity = Arc::make_mut(
+        encounter
+            .entity_uid_to_entity
+            .entry(combat_event.attacker_uid)
+            .or_default(),
+    );
+    attacker_entity.is_in_combat = true;
+
+    if combat_event.is_heal {
+        let heal_skill = attacker_entity
+            .skill_uid_to_heal_stats
+            .entry(skill_uid)
+            .or_default();
+        heal_skill.add_hit(value, is_crit, is_lucky);
+        attacker_entity.heal_skill_ranking.add(skill_uid, value);
+        attacker_entity.heal_stats.add_hit(value, is_crit, is_lucky); // update total entity heal stats
+        attacker_entity.heal_timeline.add(offset_ms, value);
+        attacker_entity.heal_rolling.add(timestamp_ms, value);
+        attacker_entity.heal_activity.add(timestamp_ms);
+        encounter.heal_stats.add_hit(value, is_crit, is_lucky); // update total encounter heal stats
+        encounter.heal_ranking.add(combat_event.attacker_uid, value);
+        return;
+    }
+
+    let dps_skill = attacker_entity
+        .skill_uid_to_dps_stats
+        .entry(skill_uid)
+        .or_default();
+    dps_skill.add_hit(value, is_crit, is_lucky);
+    attacker_entity.dps_skill_ranking.add(skill_uid, value);
+    attacker_entity.dmg_stats.add_hit(value, is_crit, is_lucky); // update total entity dmg stats
+    attacker_entity.dmg_timeline.add(offset_ms, value);
+    attacker_entity.dmg_rolling.add(timestamp_ms, value);
+    attacker_entity.dmg_activity.add(timestamp_ms);
+    encounter.dmg_stats.add_hit(value, is_crit, is_lucky); // update total encounter dmg stats
+    encounter.dmg_rolling.add(timestamp_ms, value);
+    encounter.dmg_ranking.add(combat_event.attacker_uid, value);
+    if is_boss {
+        let skill_boss_only = attacker_entity
+            .skill_uid_to_dps_stats_boss_only
+            .entry(skill_uid)
+            .or_default();
+        skill_boss_only.add_hit(value, is_crit, is_lucky);
+        attacker_entity
+            .dps_skill_ranking_boss_only
+            .add(skill_uid, value);
+        attacker_entity
+            .dmg_stats_boss_only
+            .add_hit(value, is_crit, is_lucky); // update total entity boss only dmg stats
+        attacker_entity.dmg_timeline_boss_only.add(offset_ms, value);
+        attacker_entity
+            .dmg_rolling_boss_only
+            .add(timestamp_ms, value);
+        attacker_entity.dmg_activity_boss_only.add(timestamp_ms);
+        encounter
+            .dmg_stats_boss_only
+            .add_hit(value, is_crit, is_lucky); // update total encounter dmg stats
+        encounter
+            .dmg_ranking_boss_only
+            .add(combat_event.attacker_uid, value);
+    }
+    for &filter_id in filter_ids {
+        let filter_stats = attacker_entity.dmg_filtered.entry(filter_id).or_default();
+        let skill_filtered = filter_stats
+            .skill_uid_to_stats
+            .entry(skill_uid)
+            .or_default();
+        skill_filtered.add_hit(value, is_crit, is_lucky);
+        filter_stats.skill_ranking.add(skill_uid, value);
+        filter_stats.stats.add_hit(value, is_crit, is_lucky);
+        filter_stats.timeline.add(offset_ms, value);
+        filter_stats.rolling.add(timestamp_ms, value);
+        filter_stats.activity.add(timestamp_ms);
+        let encounter_filtered = encounter.dmg_stats_filtered.entry(filter_id).or_default();
+        encounter_filtered.add_hit(value, is_crit, is_lucky);
+        encounter
+            .dmg_ranking_filtered
+            .entry(filter_id)
+            .or_default()
+            .add(combat_event.attacker_uid, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::combat_log::{
+        COMBAT_LOG_CHUNK_LEN, CombatEvent, CombatLog, recompute_encounter, record,
+    };
+    use crate::live::opcodes_models::Encounter;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_combat_log_chunks() {
+        let mut combat_log = CombatLog::default();
+        let len = COMBAT_LOG_CHUNK_LEN * 2 + 10;
+        for i in 0..len {
+            combat_log.push(&CombatEvent {
+                timestamp_ms: i as u128,
+                skill_uid: i as i32,
+                lucky_value: i as i64 % 2,
+                is_crit: i % 3 == 0,
+                ..Default::default()
+            });
+        }
+        let snapshot = combat_log.clone();
+        combat_log.shift(1000);
+        assert_eq!(snapshot.get(len - 1).unwrap().timestamp_ms, len as u128 - 1);
+        assert_eq!(
+            combat_log.get(len - 1).unwrap().timestamp_ms,
+            len as u128 + 999
+        );
+        assert_eq!(combat_log.iter().count(), len);
+        assert!(combat_log.get(len).is_none());
+
+        let json = serde_json::to_string(&combat_log).unwrap();
+        let deserialized: CombatLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, combat_log);
+        let first_of_second_chunk = deserialized.get(COMBAT_LOG_CHUNK_LEN).unwrap();
+        assert_eq!(first_of_second_chunk.skill_uid, COMBAT_LOG_CHUNK_LEN as i32);
+    }
+
+    #[test]
+    fn test_dmg_taken() {
+        let mut encounter = Encounter::default();
+        let hits = [
+            (1000, 100, false, false),
+            (3000, 50, true, false),
+            (2000, 30, false, true),
+        ];
+        for (timestamp_ms, value, is_heal, is_dead) in hits {
+            record(
+                &mut encounter,
+                &CombatEvent {
+                    timestamp_ms,
+                    attacker_uid: 1,
+                    target_uid: 2,
+                    value,
+                    is_heal,
+                    is_dead,
+                    ..Default::default()
+                },
+                &[],
+            );
+        }
+        recompute_encounter(&mut encounter, &[]);
+        assert_eq!(encounter.time_last_combat_packet_ms, 3000);
+        assert_eq!(encounter.time_last_dmg_ms, 2000);
+        let target_entity = &encounter.entity_uid_to_entity[&2];
+        assert_eq!(target_entity.dmg_taken, 130);
+        assert_eq!(target_entity.deaths, 1);
+        assert!(target_entity.is_in_combat);
+        let attacker_entity = &encounter.entity_uid_to_entity[&1];
+        assert_eq!(attacker_entity.dmg_taken, 0);
+        assert!(attacker_entity.is_in_combat);
+    }
+
+    #[test]
+    fn test_snapshot_shares_entities() {
+        let mut encounter = Encounter::default();
+        let hit = |attacker_uid| CombatEvent {
+            attacker_uid,
+            target_uid: 100,
+            value: 10,
+            ..Default::default()
+        };
+        record(&mut encounter, &hit(1), &[]);
+        record(&mut encounter, &hit(2), &[]);
+        let snapshot = encounter.clone();
+        record(&mut encounter, &hit(1), &[]);
+
+        let is_shared = |uid| {
+            Arc::ptr_eq(
+                &snapshot.entity_uid_to_entity[&uid],
+                &encounter.entity_uid_to_entity[&uid],
+            )
+        };
+        assert!(!is_shared(1));
+        assert!(is_shared(2));
+        assert_eq!(snapshot.entity_uid_to_entity[&1].dmg_stats.value, 10);
+        assert_eq!(encounter.entity_uid_to_entity[&1].dmg_stats.value, 20);
+    }
+}
@@ -1,17 +1,43 @@
 use crate::live::bptimer_state::{
     BPTimerEnabledMutex, set_bptimer_enabled as update_bptimer_state,
 };
-use crate::live::commands_models::{HeaderInfo, PlayerRow, PlayersWindow, SkillRow, SkillsWindow};
+use crate::live::commands_models::{
+    EncounterQuery, HeaderInfo, HitDistributionRow, PlayerRow, PlayersWindow, QueryResult,
+    QueryStatKind, ResetSnapshotSummary, SavedEncounter, SavedEncounterSummary, SavedEntity,
+    SkillRow, SkillsWindow, TimelineSeries, TimelineWindow,
+};
+use crate::live::encounter_export::{
+    get_encounter_export, save_encounter_csv_to_dir, save_encounter_export_to_file,
+};
+use crate::live::encounter_history::{
+    EncounterEndReason, EncounterHistoryMutex, ResetSnapshotsMutex,
+};
+use crate::live::encounter_query::project_encounter;
+use crate::live::live_main::{EncounterControl, EncounterControlSender};
+use crate::live::local_api::{self, LocalApiConfig};
+use crate::live::meter_settings::{
+    FightStartTrigger, MeterSettings, MeterSettingsMutex, TargetFilter,
+};
 use crate::live::opcodes_models::class::{Class, ClassSpec};
-use crate::live::opcodes_models::{CombatStats, Encounter, EncounterMutex, class};
-use crate::live::player_state::{PlayerCacheMutex, PlayerStateMutex};
+use crate::live::opcodes_models::{
+    Activity, CombatStats, EMPTY_FILTER_STATS, EMPTY_RANKING, Encounter, EncounterSnapshot, Entity,
+    FilterStats, HitDistribution, MONSTER_NAMES_BOSS, Ranking, RollingWindow, TIMELINE_BUCKET_MS,
+    Timeline, class,
+};
+use crate::live::player_state::{PlayerCache, PlayerCacheMutex, PlayerState, PlayerStateMutex};
+use crate::live::share_code::{decode_players_window, encode_players_window};
+use crate::live::webhook::{WebhookConfig, WebhookConfigMutex};
 use crate::packets::packet_capture::request_restart;
+use crate::packets::utils::get_timestamp_ms;
 use crate::protocol::pb::EEntityType;
 use crate::utils::modules::{
     URL_DATA_LIMIT, encode_module_data, extract_modules, save_module_data_to_file,
 };
-use log::info;
-use std::sync::MutexGuard;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+use tokio::sync::oneshot;
 
 fn nan_is_zero(value: f64) -> f64 {
     if value.is_nan() || value.is_infinite() {
@@ -23,14 +49,24 @@ fn nan_is_zero(value: f64) -> f64 {
 
 #[tauri::command]
 #[specta::specta]
-pub fn get_header_info(state: tauri::State<'_, EncounterMutex>) -> HeaderInfo {
-    let encounter = state.lock().unwrap();
+pub fn get_header_info(
+    state: tauri::State<'_, EncounterSnapshot>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+) -> HeaderInfo {
+    let rolling_window_secs = meter_settings_state.lock().unwrap().rolling_window_secs;
+    let encounter = state.borrow().clone();
+    get_header(&encounter, rolling_window_secs)
+}
+
+pub fn get_header(encounter: &Encounter, rolling_window_secs: u32) -> HeaderInfo {
     if encounter.dmg_stats.value == 0 {
         return HeaderInfo {
             total_dps: 0.0,
             total_dmg: 0.0,
             elapsed_ms: 0.0,
             time_last_combat_packet_ms: 0.0,
+            rolling_dps: 0.0,
+            rolling_window_secs: f64::from(rolling_window_secs),
         };
     }
 
@@ -44,31 +80,97 @@ pub fn get_header_info(state: tauri::State<'_, EncounterMutex>) -> HeaderInfo {
         total_dmg: encounter_stats.value as f64,
         elapsed_ms: time_elapsed_ms as f64,
         time_last_combat_packet_ms: encounter.time_last_combat_packet_ms as f64,
+        rolling_dps: get_rolling_value_per_sec(
+            &encounter.dmg_rolling,
+            encounter,
+            rolling_window_secs,
+        ),
+        rolling_window_secs: f64::from(rolling_window_secs),
     }
 }
 
+/// Value per second over the last `window_secs` seconds of combat (shorter if the fight is shorter).
+fn get_rolling_value_per_sec(
+    rolling: &RollingWindow,
+    encounter: &Encounter,
+    window_secs: u32,
+) -> f64 {
+    let time_elapsed_ms = encounter.time_last_combat_packet_ms - encounter.time_fight_start_ms;
+    let window_secs = u128::from(window_secs).min(time_elapsed_ms / 1000 + 1);
+    nan_is_zero(
+        rolling.sum(encounter.time_last_combat_packet_ms, window_secs as u32) as f64
+            / window_secs as f64,
+    )
+}
+
 #[tauri::command]
 #[specta::specta]
-pub fn hard_reset(state: tauri::State<'_, EncounterMutex>) {
-    let mut encounter = state.lock().unwrap();
-    encounter.clone_from(&Encounter::default());
+pub fn hard_reset(control_state: tauri::State<'_, EncounterControlSender>) {
+    send_encounter_control(&control_state, EncounterControl::Reset);
     request_restart();
     info!("Hard Reset");
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn reset_encounter(state: tauri::State<'_, EncounterMutex>) {
-    let mut encounter = state.lock().unwrap();
-    encounter.clone_from(&Encounter::default());
-    info!("encounter reset");
+pub fn reset_encounter(control_state: tauri::State<'_, EncounterControlSender>) {
+    send_encounter_control(&control_state, EncounterControl::Reset);
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn toggle_pause_encounter(state: tauri::State<'_, EncounterMutex>) {
-    let mut encounter = state.lock().unwrap();
-    encounter.is_encounter_paused = !encounter.is_encounter_paused;
+pub fn get_reset_snapshots(
+    reset_snapshots_state: tauri::State<'_, ResetSnapshotsMutex>,
+) -> Vec<ResetSnapshotSummary> {
+    let reset_snapshots = reset_snapshots_state.lock().unwrap();
+    reset_snapshots
+        .iter()
+        .enumerate()
+        .map(|(index, encounter)| ResetSnapshotSummary {
+            index: index as f64,
+            time_fight_start_ms: encounter.time_fight_start_ms as f64,
+            elapsed_ms: (encounter.time_last_combat_packet_ms - encounter.time_fight_start_ms)
+                as f64,
+            total_dmg: encounter.dmg_stats.value as f64,
+        })
+        .collect()
+}
+
+/// Brings back the encounter cleared by a reset, `index` 0 being the most recent reset.
+/// The current encounter takes its place in the snapshots so the restore can be undone too.
+#[tauri::command]
+#[specta::specta]
+pub async fn restore_encounter(
+    control_state: tauri::State<'_, EncounterControlSender>,
+    index: u32,
+) -> Result<(), String> {
+    let (reply, reply_receiver) = oneshot::channel();
+    let control = EncounterControl::Restore {
+        index: index as usize,
+        reply,
+    };
+    control_state
+        .send(control)
+        .map_err(|_| "Packet processing is not running".to_string())?;
+    reply_receiver
+        .await
+        .map_err(|_| "Packet processing stopped before restoring".to_string())?
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn toggle_pause_encounter(control_state: tauri::State<'_, EncounterControlSender>) {
+    let timestamp_ms = get_timestamp_ms();
+    send_encounter_control(
+        &control_state,
+        EncounterControl::TogglePause { timestamp_ms },
+    );
+}
+
+fn send_encounter_control(control_sender: &EncounterControlSender, control: EncounterControl) {
+    if control_sender.send(control).is_err() {
+        warn!("Packet processing is not running, encounter control dropped");
+    }
 }
 
 #[tauri::command]
@@ -82,150 +184,475 @@ pub enum StatType {
     Dmg,
     DmgBossOnly,
     Heal,
+    DmgFiltered(u32), // TargetFilter::id
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_dps_player_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
 ) -> PlayersWindow {
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
-    get_player_window(encounter, StatType::Dmg, &player_cache, &player_state)
+    get_player_window(
+        encounter,
+        StatType::Dmg,
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    )
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_heal_player_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
 ) -> PlayersWindow {
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
-    get_player_window(encounter, StatType::Heal, &player_cache, &player_state)
+    get_player_window(
+        encounter,
+        StatType::Heal,
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    )
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_dps_filtered_player_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    filter_id: u32,
+) -> PlayersWindow {
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
+    let player_state = player_state.lock().unwrap();
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_player_window(
+        encounter,
+        StatType::DmgFiltered(filter_id),
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    )
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_dps_boss_only_player_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
 ) -> PlayersWindow {
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
     get_player_window(
         encounter,
         StatType::DmgBossOnly,
         &player_cache,
         &player_state,
+        &meter_settings,
     )
 }
 
-pub fn get_player_window(
-    encounter: MutexGuard<Encounter>,
+fn get_entity_stats(entity: &Entity, stat_type: StatType) -> &CombatStats {
+    match stat_type {
+        StatType::Dmg => &entity.dmg_stats,
+        StatType::DmgBossOnly => &entity.dmg_stats_boss_only,
+        StatType::Heal => &entity.heal_stats,
+        StatType::DmgFiltered(filter_id) => &get_entity_filter_stats(entity, filter_id).stats,
+    }
+}
+
+fn get_encounter_stats(encounter: &Encounter, stat_type: StatType) -> &CombatStats {
+    match stat_type {
+        StatType::Dmg => &encounter.dmg_stats,
+        StatType::DmgBossOnly => &encounter.dmg_stats_boss_only,
+        StatType::Heal => &encounter.heal_stats,
+        StatType::DmgFiltered(filter_id) => encounter
+            .dmg_stats_filtered
+            .get(&filter_id)
+            .unwrap_or(&EMPTY_FILTER_STATS.stats),
+    }
+}
+
+fn get_entity_skill_stats(entity: &Entity, stat_type: StatType) -> &HashMap<i32, CombatStats> {
+    match stat_type {
+        StatType::Dmg => &entity.skill_uid_to_dps_stats,
+        StatType::DmgBossOnly => &entity.skill_uid_to_dps_stats_boss_only,
+        StatType::Heal => &entity.skill_uid_to_heal_stats,
+        StatType::DmgFiltered(filter_id) => {
+            &get_entity_filter_stats(entity, filter_id).skill_uid_to_stats
+        }
+    }
+}
+
+fn get_encounter_ranking(encounter: &Encounter, stat_type: StatType) -> &Ranking<i64> {
+    match stat_type {
+        StatType::Dmg => &encounter.dmg_ranking,
+        StatType::DmgBossOnly => &encounter.dmg_ranking_boss_only,
+        StatType::Heal => &encounter.heal_ranking,
+        StatType::DmgFiltered(filter_id) => encounter
+            .dmg_ranking_filtered
+            .get(&filter_id)
+            .unwrap_or(&EMPTY_RANKING),
+    }
+}
+
+fn get_entity_skill_ranking(entity: &Entity, stat_type: StatType) -> &Ranking<i32> {
+    match stat_type {
+        StatType::Dmg => &entity.dps_skill_ranking,
+        StatType::DmgBossOnly => &entity.dps_skill_ranking_boss_only,
+        StatType::Heal => &entity.heal_skill_ranking,
+        StatType::DmgFiltered(filter_id) => {
+            &get_entity_filter_stats(entity, filter_id).skill_ranking
+        }
+    }
+}
+
+fn get_entity_filter_stats(entity: &Entity, filter_id: u32) -> &FilterStats {
+    entity
+        .dmg_filtered
+        .get(&filter_id)
+        .unwrap_or(&EMPTY_FILTER_STATS)
+}
+
+fn get_entity_timeline(entity: &Entity, stat_type: StatType) -> &Timeline {
+    match stat_type {
+        StatType::Dmg => &entity.dmg_timeline,
+        StatType::DmgBossOnly => &entity.dmg_timeline_boss_only,
+        StatType::Heal => &entity.heal_timeline,
+        StatType::DmgFiltered(filter_id) => &get_entity_filter_stats(entity, filter_id).timeline,
+    }
+}
+
+/// Per-skill multipliers averaged by hit count. Skills hit for very different amounts, so comparing
+/// a player's overall crit average against their overall normal average would mostly measure skill mix.
+fn get_weighted_multiplier(
+    skill_uid_to_stats: &HashMap<i32, CombatStats>,
+    multiplier_fn: fn(&CombatStats) -> Option<f64>,
+    hits_fn: fn(&CombatStats) -> i64,
+) -> f64 {
+    let mut weighted_sum = 0.0;
+    let mut total_hits = 0;
+    for skill_stats in skill_uid_to_stats.values() {
+        if let Some(multiplier) = multiplier_fn(skill_stats) {
+            let hits = hits_fn(skill_stats);
+            weighted_sum += multiplier * hits as f64;
+            total_hits += hits;
+        }
+    }
+    nan_is_zero(weighted_sum / total_hits as f64)
+}
+
+fn get_hit_distribution_row(dist: &HitDistribution) -> HitDistributionRow {
+    HitDistributionRow {
+        hits: dist.hits as f64,
+        min: dist.min as f64,
+        max: dist.max as f64,
+        mean: dist.mean(),
+        p50: dist.quantile(0.50),
+        p90: dist.quantile(0.90),
+        p99: dist.quantile(0.99),
+    }
+}
+
+fn get_entity_rolling(entity: &Entity, stat_type: StatType) -> &RollingWindow {
+    match stat_type {
+        StatType::Dmg => &entity.dmg_rolling,
+        StatType::DmgBossOnly => &entity.dmg_rolling_boss_only,
+        StatType::Heal => &entity.heal_rolling,
+        StatType::DmgFiltered(filter_id) => &get_entity_filter_stats(entity, filter_id).rolling,
+    }
+}
+
+fn get_entity_activity(entity: &Entity, stat_type: StatType) -> &Activity {
+    match stat_type {
+        StatType::Dmg => &entity.dmg_activity,
+        StatType::DmgBossOnly => &entity.dmg_activity_boss_only,
+        StatType::Heal => &entity.heal_activity,
+        StatType::DmgFiltered(filter_id) => &get_entity_filter_stats(entity, filter_id).activity,
+    }
+}
+
+fn get_player_row(
+    encounter: &Encounter,
+    entity_uid: i64,
+    entity: &Entity,
     stat_type: StatType,
-    player_cache: &std::sync::MutexGuard<crate::live::player_state::PlayerCache>,
-    player_state: &std::sync::MutexGuard<crate::live::player_state::PlayerState>,
-) -> PlayersWindow {
+    player_cache: &PlayerCache,
+    meter_settings: &MeterSettings,
+) -> PlayerRow {
+    // Select stats per player
+    let entity_stats = get_entity_stats(entity, stat_type);
+    let skill_uid_to_stats = get_entity_skill_stats(entity, stat_type);
+    let time_active_ms = get_entity_activity(entity, stat_type).active_ms();
+    let time_active_secs = time_active_ms as f64 / 1000.0;
+
+    let mut player_row = PlayerRow {
+        uid: entity_uid as f64,
+        name: entity
+            .name
+            .clone()
+            .or_else(|| player_cache.get_name(entity_uid))
+            .unwrap_or_else(|| match entity.summoner_uid {
+                Some(summoner_uid) => format!(
+                    "Summon of {}",
+                    encounter
+                        .entity_uid_to_entity
+                        .get(&summoner_uid)
+                        .and_then(|summoner| summoner.name.clone())
+                        .or_else(|| player_cache.get_name(summoner_uid))
+                        .unwrap_or_else(|| format!("Player {summoner_uid}"))
+                ),
+                None => format!("Player {entity_uid}"),
+            }),
+        class_name: class::get_class_name(
+            entity
+                .class
+                .or_else(|| player_cache.get_class(entity_uid))
+                .unwrap_or(Class::Unknown),
+        )
+        .into(),
+        class_spec_name: class::get_class_spec(
+            entity
+                .class_spec
+                .or_else(|| player_cache.get_class_spec(entity_uid))
+                .unwrap_or(ClassSpec::Unknown),
+        )
+        .into(),
+        ability_score: f64::from(
+            entity
+                .ability_score
+                .or_else(|| player_cache.get_ability_score(entity_uid))
+                .unwrap_or(-1),
+        ),
+        total_value: entity_stats.value as f64,
+        crit_rate: nan_is_zero(entity_stats.crit_hits as f64 / entity_stats.hits as f64 * 100.0),
+        crit_value_rate: nan_is_zero(
+            entity_stats.crit_value as f64 / entity_stats.value as f64 * 100.0,
+        ),
+        lucky_rate: nan_is_zero(entity_stats.lucky_hits as f64 / entity_stats.hits as f64 * 100.0),
+        lucky_value_rate: nan_is_zero(
+            entity_stats.lucky_value as f64 / entity_stats.value as f64 * 100.0,
+        ),
+        crit_hits: entity_stats.crit_hits as f64,
+        crit_value: entity_stats.crit_value as f64,
+        lucky_hits: entity_stats.lucky_hits as f64,
+        lucky_value: entity_stats.lucky_value as f64,
+        hits: entity_stats.hits as f64,
+        active_time_ms: time_active_ms as f64,
+        active_value_per_sec: nan_is_zero(entity_stats.value as f64 / time_active_secs),
+        crit_multiplier: get_weighted_multiplier(
+            skill_uid_to_stats,
+            CombatStats::get_crit_multiplier,
+            CombatStats::get_crit_only_hits,
+        ),
+        lucky_multiplier: get_weighted_multiplier(
+            skill_uid_to_stats,
+            CombatStats::get_lucky_multiplier,
+            CombatStats::get_lucky_only_hits,
+        ),
+        ..Default::default()
+    };
+    update_player_row_clock(
+        &mut player_row,
+        encounter,
+        entity,
+        stat_type,
+        meter_settings,
+    );
+    player_row
+}
+
+// Sets the values that move with the fight clock or the encounter total, they change without
+// the entity changing. Returns whether any of them changed.
+fn update_player_row_clock(
+    player_row: &mut PlayerRow,
+    encounter: &Encounter,
+    entity: &Entity,
+    stat_type: StatType,
+    meter_settings: &MeterSettings,
+) -> bool {
     let time_elapsed_ms = encounter.time_last_combat_packet_ms - encounter.time_fight_start_ms;
     let time_elapsed_secs = time_elapsed_ms as f64 / 1000.0;
+    let entity_stats = get_entity_stats(entity, stat_type);
+    let encounter_stats = get_encounter_stats(encounter, stat_type);
+    let time_active_ms = get_entity_activity(entity, stat_type).active_ms();
 
-    let mut player_window = PlayersWindow {
-        player_rows: Vec::new(),
-        local_player_uid: player_state.get_uid() as f64,
-        top_value: 0.0,
-    };
-    for (&entity_uid, entity) in &encounter.entity_uid_to_entity {
-        // Select stats per player and encounter
-        let (entity_stats, encounter_stats) = match stat_type {
-            StatType::Dmg => (&entity.dmg_stats, &encounter.dmg_stats),
-            StatType::DmgBossOnly => (&entity.dmg_stats_boss_only, &encounter.dmg_stats_boss_only),
-            StatType::Heal => (&entity.heal_stats, &encounter.heal_stats),
+    let clock_values = [
+        nan_is_zero(entity_stats.value as f64 / time_elapsed_secs),
+        nan_is_zero(entity_stats.value as f64 / encounter_stats.value as f64 * 100.0),
+        nan_is_zero(entity_stats.hits as f64 / time_elapsed_secs * 60.0),
+        get_rolling_value_per_sec(
+            get_entity_rolling(entity, stat_type),
+            encounter,
+            meter_settings.rolling_window_secs,
+        ),
+        nan_is_zero(time_active_ms as f64 / time_elapsed_ms as f64 * 100.0).min(100.0),
+    ];
+    let row_values = [
+        &mut player_row.value_per_sec,
+        &mut player_row.value_pct,
+        &mut player_row.hits_per_minute,
+        &mut player_row.rolling_value_per_sec,
+        &mut player_row.uptime_pct,
+    ];
+    let mut is_changed = false;
+    for (row_value, clock_value) in row_values.into_iter().zip(clock_values) {
+        is_changed |= *row_value != clock_value;
+        *row_value = clock_value;
+    }
+    is_changed
+}
+
+/// Last players window of one stat type and the entities its rows were built from. Entities
+/// are copied by `Arc::make_mut` when they change, so an entity that is still the same `Arc` got
+/// no new event and its row only needs the clock values updated, the rest is kept as is.
+#[derive(Default)]
+pub struct PlayerWindowCache {
+    pub window: PlayersWindow,
+    uid_to_entity: HashMap<i64, Arc<Entity>>,
+    // Rows that changed in the last update, as indices into `window.player_rows`
+    pub changed_row_indices: Vec<usize>,
+    // Rows were added, removed or moved, or the local player changed
+    pub is_reordered: bool,
+}
+
+impl PlayerWindowCache {
+    pub fn is_changed(&self) -> bool {
+        self.is_reordered || !self.changed_row_indices.is_empty()
+    }
+
+    /// Brings `window` up to date with `encounter`. Only rows of entities that changed since the
+    /// last update are rebuilt, so the cost follows the number of rows that changed.
+    pub fn update(
+        &mut self,
+        encounter: &Encounter,
+        stat_type: StatType,
+        player_cache: &PlayerCache,
+        player_state: &PlayerState,
+        meter_settings: &MeterSettings,
+    ) {
+        let local_player_uid = player_state.get_uid() as f64;
+        self.is_reordered = self.window.local_player_uid != local_player_uid;
+        self.changed_row_indices.clear();
+        let mut uid_to_last_row = std::mem::take(&mut self.window.player_rows)
+            .into_iter()
+            .enumerate()
+            .map(|(row_index, player_row)| (player_row.uid as i64, (row_index, player_row)))
+            .collect::<HashMap<_, _>>();
+        let mut uid_to_last_entity = std::mem::take(&mut self.uid_to_entity);
+        self.window = PlayersWindow {
+            player_rows: Vec::with_capacity(uid_to_last_row.len()),
+            local_player_uid,
+            top_value: 0.0,
         };
-        let is_player = entity.entity_type == EEntityType::EntChar;
-        let did_damage = entity_stats.value > 0;
-        if !is_player || !did_damage {
-            continue;
+
+        // Rankings are kept in order while packets are processed, so rows come out sorted
+        for entity_uid in get_encounter_ranking(encounter, stat_type).keys() {
+            let Some(entity) = encounter.entity_uid_to_entity.get(&entity_uid) else {
+                continue;
+            };
+            let is_player =
+                entity.entity_type == EEntityType::EntChar || entity.summoner_uid.is_some();
+            let did_damage = get_entity_stats(entity, stat_type).value > 0;
+            if !is_player || !did_damage {
+                continue;
+            }
+            let row_index = self.window.player_rows.len();
+            let (last_row_index, last_row) = uid_to_last_row.remove(&entity_uid).unzip();
+            self.is_reordered |= last_row_index != Some(row_index);
+            let is_same_entity = uid_to_last_entity
+                .remove(&entity_uid)
+                .is_some_and(|last_entity| Arc::ptr_eq(&last_entity, entity));
+            let player_row = match last_row {
+                Some(mut player_row) if is_same_entity => {
+                    if update_player_row_clock(
+                        &mut player_row,
+                        encounter,
+                        entity,
+                        stat_type,
+                        meter_settings,
+                    ) {
+                        self.changed_row_indices.push(row_index);
+                    }
+                    player_row
+                }
+                last_row => {
+                    let player_row = get_player_row(
+                        encounter,
+                        entity_uid,
+                        entity,
+                        stat_type,
+                        player_cache,
+                        meter_settings,
+                    );
+                    if last_row.as_ref() != Some(&player_row) {
+                        self.changed_row_indices.push(row_index);
+                    }
+                    player_row
+                }
+            };
+            self.uid_to_entity.insert(entity_uid, entity.clone());
+            self.window.top_value = self.window.top_value.max(player_row.total_value);
+            self.window.player_rows.push(player_row);
         }
-        player_window.top_value = player_window.top_value.max(entity_stats.value as f64);
-        let damage_row = PlayerRow {
-            uid: entity_uid as f64,
-            name: entity
-                .name
-                .clone()
-                .or_else(|| player_cache.get_name(entity_uid))
-                .unwrap_or_else(|| format!("Player {entity_uid}")),
-            class_name: class::get_class_name(
-                entity
-                    .class
-                    .or_else(|| player_cache.get_class(entity_uid))
-                    .unwrap_or(Class::Unknown),
-            ),
-            class_spec_name: class::get_class_spec(
-                entity
-                    .class_spec
-                    .or_else(|| player_cache.get_class_spec(entity_uid))
-                    .unwrap_or(ClassSpec::Unknown),
-            ),
-            ability_score: f64::from(
-                entity
-                    .ability_score
-                    .or_else(|| player_cache.get_ability_score(entity_uid))
-                    .unwrap_or(-1),
-            ),
-            total_value: entity_stats.value as f64,
-            value_per_sec: nan_is_zero(entity_stats.value as f64 / time_elapsed_secs),
-            value_pct: nan_is_zero(
-                entity_stats.value as f64 / encounter_stats.value as f64 * 100.0,
-            ),
-            crit_rate: nan_is_zero(
-                entity_stats.crit_hits as f64 / entity_stats.hits as f64 * 100.0,
-            ),
-            crit_value_rate: nan_is_zero(
-                entity_stats.crit_value as f64 / entity_stats.value as f64 * 100.0,
-            ),
-            lucky_rate: nan_is_zero(
-                entity_stats.lucky_hits as f64 / entity_stats.hits as f64 * 100.0,
-            ),
-            lucky_value_rate: nan_is_zero(
-                entity_stats.lucky_value as f64 / entity_stats.value as f64 * 100.0,
-            ),
-            hits: entity_stats.hits as f64,
-            hits_per_minute: nan_is_zero(entity_stats.hits as f64 / time_elapsed_secs * 60.0),
-        };
-        player_window.player_rows.push(damage_row);
+        self.is_reordered |= !uid_to_last_row.is_empty();
     }
-    drop(encounter); // drop lock before expensive sort
-
-    // Sort skills descending by damage dealt
-    player_window.player_rows.sort_by(|this_row, other_row| {
-        other_row
-            .total_value
-            .partial_cmp(&this_row.total_value)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
+}
 
-    player_window
+/// Builds a players window from scratch, see `PlayerWindowCache` to keep one up to date instead
+pub fn get_player_window(
+    encounter: impl Deref<Target = Encounter>,
+    stat_type: StatType,
+    player_cache: &PlayerCache,
+    player_state: &PlayerState,
+    meter_settings: &MeterSettings,
+) -> PlayersWindow {
+    let mut player_window_cache = PlayerWindowCache::default();
+    player_window_cache.update(
+        &encounter,
+        stat_type,
+        player_cache,
+        player_state,
+        meter_settings,
+    );
+    player_window_cache.window
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_dps_skill_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
     player_uid_str: &str,
 ) -> Result<SkillsWindow, String> {
-    let player_uid = player_uid_str.parse().unwrap();
+    let player_uid = player_uid_str
+        .parse()
+        .map_err(|e| format!("Invalid player uid {player_uid_str}: {e}"))?;
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
     get_skill_window(
         encounter,
@@ -233,20 +660,25 @@ pub fn get_dps_skill_window(
         StatType::Dmg,
         &player_cache,
         &player_state,
+        &meter_settings,
     )
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_dps_boss_only_skill_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
     player_uid_str: &str,
 ) -> Result<SkillsWindow, String> {
-    let player_uid = player_uid_str.parse().unwrap();
+    let player_uid = player_uid_str
+        .parse()
+        .map_err(|e| format!("Invalid player uid {player_uid_str}: {e}"))?;
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
     get_skill_window(
         encounter,
@@ -254,20 +686,25 @@ pub fn get_dps_boss_only_skill_window(
         StatType::DmgBossOnly,
         &player_cache,
         &player_state,
+        &meter_settings,
     )
 }
 
 #[tauri::command]
 #[specta::specta]
 pub fn get_heal_skill_window(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
     player_cache_state: tauri::State<'_, PlayerCacheMutex>,
     player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
     player_uid_str: &str,
 ) -> Result<SkillsWindow, String> {
-    let player_uid = player_uid_str.parse().unwrap();
+    let player_uid = player_uid_str
+        .parse()
+        .map_err(|e| format!("Invalid player uid {player_uid_str}: {e}"))?;
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
     let player_state = player_state.lock().unwrap();
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let player_cache = player_cache_state.lock().unwrap();
     get_skill_window(
         encounter,
@@ -275,15 +712,44 @@ pub fn get_heal_skill_window(
         StatType::Heal,
         &player_cache,
         &player_state,
+        &meter_settings,
+    )
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_dps_filtered_skill_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    filter_id: u32,
+    player_uid_str: &str,
+) -> Result<SkillsWindow, String> {
+    let player_uid = player_uid_str
+        .parse()
+        .map_err(|e| format!("Invalid player uid {player_uid_str}: {e}"))?;
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
+    let player_state = player_state.lock().unwrap();
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_skill_window(
+        encounter,
+        player_uid,
+        StatType::DmgFiltered(filter_id),
+        &player_cache,
+        &player_state,
+        &meter_settings,
     )
 }
 
 pub fn get_skill_window(
-    encounter: MutexGuard<Encounter>,
+    encounter: impl Deref<Target = Encounter>,
     player_uid: i64,
     stat_type: StatType,
-    player_cache: &std::sync::MutexGuard<crate::live::player_state::PlayerCache>,
-    player_state: &std::sync::MutexGuard<crate::live::player_state::PlayerState>,
+    player_cache: &PlayerCache,
+    player_state: &PlayerState,
+    meter_settings: &MeterSettings,
 ) -> Result<SkillsWindow, String> {
     let Some(player) = encounter.entity_uid_to_entity.get(&player_uid) else {
         return Err(format!("Could not find player with uid {player_uid}"));
@@ -292,78 +758,29 @@ pub fn get_skill_window(
     let time_elapsed_ms = encounter.time_last_combat_packet_ms - encounter.time_fight_start_ms;
     let time_elapsed_secs = time_elapsed_ms as f64 / 1000.0;
 
-    let (player_stats, encounter_stats, skill_uid_to_stats) = match stat_type {
-        StatType::Dmg => (
-            &player.dmg_stats,
-            &encounter.dmg_stats,
-            &player.skill_uid_to_dps_stats,
-        ),
-        StatType::DmgBossOnly => (
-            &player.dmg_stats_boss_only,
-            &encounter.dmg_stats_boss_only,
-            &player.skill_uid_to_dps_stats_boss_only,
-        ),
-        StatType::Heal => (
-            &player.heal_stats,
-            &encounter.heal_stats,
-            &player.skill_uid_to_heal_stats,
-        ),
-    };
+    let player_stats = get_entity_stats(player, stat_type);
+    let skill_uid_to_stats = get_entity_skill_stats(player, stat_type);
 
     // Player DPS Stats
     let mut skill_window = SkillsWindow {
-        inspected_player: PlayerRow {
-            uid: player_uid as f64,
-            name: player
-                .name
-                .clone()
-                .or_else(|| player_cache.get_name(player_uid))
-                .unwrap_or_else(|| format!("Player {player_uid}")),
-            class_name: class::get_class_name(
-                player
-                    .class
-                    .or_else(|| player_cache.get_class(player_uid))
-                    .unwrap_or(Class::Unknown),
-            ),
-            class_spec_name: class::get_class_spec(
-                player
-                    .class_spec
-                    .or_else(|| player_cache.get_class_spec(player_uid))
-                    .unwrap_or(ClassSpec::Unknown),
-            ),
-            ability_score: f64::from(
-                player
-                    .ability_score
-                    .or_else(|| player_cache.get_ability_score(player_uid))
-                    .unwrap_or(-1),
-            ),
-            total_value: player_stats.value as f64,
-            value_per_sec: nan_is_zero(player_stats.value as f64 / time_elapsed_secs),
-            value_pct: nan_is_zero(
-                player_stats.value as f64 / encounter_stats.value as f64 * 100.0,
-            ),
-            crit_rate: nan_is_zero(
-                player_stats.crit_hits as f64 / player_stats.hits as f64 * 100.0,
-            ),
-            crit_value_rate: nan_is_zero(
-                player_stats.crit_value as f64 / player_stats.value as f64 * 100.0,
-            ),
-            lucky_rate: nan_is_zero(
-                player_stats.lucky_hits as f64 / player_stats.hits as f64 * 100.0,
-            ),
-            lucky_value_rate: nan_is_zero(
-                player_stats.lucky_value as f64 / player_stats.value as f64 * 100.0,
-            ),
-            hits: player_stats.hits as f64,
-            hits_per_minute: nan_is_zero(player_stats.hits as f64 / time_elapsed_secs * 60.0),
-        },
+        inspected_player: get_player_row(
+            &encounter,
+            player_uid,
+            player,
+            stat_type,
+            player_cache,
+            meter_settings,
+        ),
         local_player_uid: player_state.get_uid() as f64,
         skill_rows: Vec::new(),
         top_value: 0.0,
     };
 
     // Skills for this player
-    for (&skill_uid, skill_stat) in skill_uid_to_stats {
+    for skill_uid in get_entity_skill_ranking(player, stat_type).keys() {
+        let Some(skill_stat) = skill_uid_to_stats.get(&skill_uid) else {
+            continue;
+        };
         skill_window.top_value = skill_window.top_value.max(skill_stat.value as f64);
         let skill_row = SkillRow {
             uid: f64::from(skill_uid),
@@ -379,22 +796,496 @@ pub fn get_skill_window(
             lucky_value_rate: nan_is_zero(
                 skill_stat.lucky_value as f64 / skill_stat.value as f64 * 100.0,
             ),
+            crit_hits: skill_stat.crit_hits as f64,
+            crit_value: skill_stat.crit_value as f64,
+            lucky_hits: skill_stat.lucky_hits as f64,
+            lucky_value: skill_stat.lucky_value as f64,
             hits: skill_stat.hits as f64,
             hits_per_minute: nan_is_zero(skill_stat.hits as f64 / time_elapsed_secs * 60.0),
+            crit_multiplier: skill_stat.get_crit_multiplier().unwrap_or(0.0),
+            lucky_multiplier: skill_stat.get_lucky_multiplier().unwrap_or(0.0),
+            normal_hit_dist: get_hit_distribution_row(&skill_stat.normal_hit_dist),
+            crit_hit_dist: get_hit_distribution_row(&skill_stat.crit_hit_dist),
+            lucky_hit_dist: get_hit_distribution_row(&skill_stat.lucky_hit_dist),
+            crit_lucky_hit_dist: get_hit_distribution_row(&skill_stat.crit_lucky_hit_dist),
         };
         skill_window.skill_rows.push(skill_row);
     }
-    drop(encounter); // drop before expensive sort
-
-    // Sort skills descending by damage dealt
-    skill_window.skill_rows.sort_by(|this_row, other_row| {
-        other_row
-            .total_value
-            .partial_cmp(&this_row.total_value) // descending
-            .unwrap_or(std::cmp::Ordering::Equal)
+    Ok(skill_window)
+}
+
+/// Snapshot of every window of a finished encounter, so it can be viewed without the live state
+pub fn get_saved_encounter(
+    encounter: &Encounter,
+    end_reason: Option<EncounterEndReason>,
+    player_cache: &PlayerCache,
+    player_state: &PlayerState,
+    meter_settings: &MeterSettings,
+) -> SavedEncounter {
+    let get_skill_windows = |players_window: &PlayersWindow, stat_type: StatType| {
+        players_window
+            .player_rows
+            .iter()
+            .filter_map(|player_row| {
+                let player_uid = player_row.uid as i64;
+                get_skill_window(
+                    encounter,
+                    player_uid,
+                    stat_type,
+                    player_cache,
+                    player_state,
+                    meter_settings,
+                )
+                .ok()
+                .map(|skill_window| (player_uid.to_string(), skill_window))
+            })
+            .collect::<HashMap<_, _>>()
+    };
+
+    let dps_players = get_player_window(
+        encounter,
+        StatType::Dmg,
+        player_cache,
+        player_state,
+        meter_settings,
+    );
+    let dps_boss_only_players = get_player_window(
+        encounter,
+        StatType::DmgBossOnly,
+        player_cache,
+        player_state,
+        meter_settings,
+    );
+    let heal_players = get_player_window(
+        encounter,
+        StatType::Heal,
+        player_cache,
+        player_state,
+        meter_settings,
+    );
+
+    SavedEncounter {
+        id: encounter.time_fight_start_ms.to_string(),
+        name: chrono::DateTime::from_timestamp_millis(encounter.time_fight_start_ms as i64)
+            .map(|start| {
+                start
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "Encounter".to_string()),
+        end_reason,
+        time_fight_start_ms: encounter.time_fight_start_ms as f64,
+        time_last_combat_packet_ms: encounter.time_last_combat_packet_ms as f64,
+        header_info: get_header(encounter, meter_settings.rolling_window_secs),
+        dps_skills: get_skill_windows(&dps_players, StatType::Dmg),
+        dps_boss_only_skills: get_skill_windows(&dps_boss_only_players, StatType::DmgBossOnly),
+        heal_skills: get_skill_windows(&heal_players, StatType::Heal),
+        dps_players,
+        dps_boss_only_players,
+        heal_players,
+        line_id: encounter.line_id,
+        level_map_id: encounter.level_map_id,
+        entities: get_saved_entities(encounter, player_cache),
+    }
+}
+
+// Entities carried over from earlier fights are left out unless they attacked or got hit in this one
+fn get_saved_entities(encounter: &Encounter, player_cache: &PlayerCache) -> Vec<SavedEntity> {
+    let mut saved_entities = encounter
+        .entity_uid_to_entity
+        .iter()
+        .filter(|(_, entity)| entity.is_in_combat)
+        .map(|(&uid, entity)| SavedEntity {
+            uid: uid as f64,
+            entity_type: entity.entity_type.as_str_name().into(),
+            name: entity.name.clone().or_else(|| player_cache.get_name(uid)),
+            monster_id: entity.monster_id,
+            is_boss: entity
+                .monster_id
+                .is_some_and(|monster_id| MONSTER_NAMES_BOSS.contains_key(&monster_id)),
+            max_hp: entity.max_hp.map(|max_hp| max_hp as f64),
+            dmg_taken: entity.dmg_taken as f64,
+            deaths: entity.deaths,
+        })
+        .collect::<Vec<_>>();
+    saved_entities.sort_by(|this, other| {
+        other
+            .dmg_taken
+            .total_cmp(&this.dmg_taken)
+            .then(this.uid.total_cmp(&other.uid))
     });
+    saved_entities
+}
 
-    Ok(skill_window)
+#[tauri::command]
+#[specta::specta]
+pub fn get_encounter_history(
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+) -> Result<Vec<SavedEncounterSummary>, String> {
+    let history = history_state.lock().unwrap();
+    history
+        .list()
+        .map_err(|e| format!("Failed to list encounters: {e}"))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn load_encounter(
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+    id: String,
+) -> Result<SavedEncounter, String> {
+    let history = history_state.lock().unwrap();
+    history
+        .load(&id)
+        .map_err(|e| format!("Failed to load encounter {id}: {e}"))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn rename_encounter(
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+    id: String,
+    name: String,
+) -> Result<(), String> {
+    let history = history_state.lock().unwrap();
+    history
+        .rename(&id, name)
+        .map_err(|e| format!("Failed to rename encounter {id}: {e}"))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn delete_encounter(
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+    id: String,
+) -> Result<(), String> {
+    let history = history_state.lock().unwrap();
+    history
+        .delete(&id)
+        .map_err(|e| format!("Failed to delete encounter {id}: {e}"))?;
+    info!("encounter {id} deleted");
+    Ok(())
+}
+
+/// Writes the saved encounter `id`, or the current one if `id` is None, to a JSON file in the
+/// Downloads folder and returns its path. See `EncounterExport` for the schema.
+#[tauri::command]
+#[specta::specta]
+pub fn export_encounter(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+    id: Option<String>,
+) -> Result<String, String> {
+    let saved_encounter = get_encounter_to_export(
+        &state,
+        &player_cache_state,
+        &player_state,
+        &meter_settings_state,
+        &history_state,
+        id,
+    )?;
+    let encounter_export = get_encounter_export(&saved_encounter);
+    let path = save_encounter_export_to_file(&encounter_export)
+        .map_err(|e| format!("Failed to save encounter export: {e}"))?;
+    info!("Encounter exported to {}", path.display());
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Writes the player and skill tables of the saved encounter `id`, or the current one if `id`
+/// is None, to CSV files in a new folder in Downloads and returns the folder path.
+/// There is one players and one skills file each for dmg, boss-only dmg and heal.
+#[tauri::command]
+#[specta::specta]
+pub fn export_encounter_csv(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    history_state: tauri::State<'_, EncounterHistoryMutex>,
+    id: Option<String>,
+) -> Result<String, String> {
+    let saved_encounter = get_encounter_to_export(
+        &state,
+        &player_cache_state,
+        &player_state,
+        &meter_settings_state,
+        &history_state,
+        id,
+    )?;
+    let path = save_encounter_csv_to_dir(&saved_encounter)
+        .map_err(|e| format!("Failed to save encounter CSV export: {e}"))?;
+    info!("Encounter exported to {}", path.display());
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn get_encounter_to_export(
+    state: &EncounterSnapshot,
+    player_cache_state: &PlayerCacheMutex,
+    player_state: &PlayerStateMutex,
+    meter_settings_state: &MeterSettingsMutex,
+    history_state: &EncounterHistoryMutex,
+    id: Option<String>,
+) -> Result<SavedEncounter, String> {
+    if let Some(id) = id {
+        let history = history_state.lock().unwrap();
+        return history
+            .load(&id)
+            .map_err(|e| format!("Failed to load encounter {id}: {e}"));
+    }
+    let encounter = state.borrow().clone();
+    if encounter.time_fight_start_ms == 0 {
+        return Err("No encounter to export yet".to_string());
+    }
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
+    let player_state = player_state.lock().unwrap();
+    let player_cache = player_cache_state.lock().unwrap();
+    Ok(get_saved_encounter(
+        &encounter,
+        None, // still running
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    ))
+}
+
+/// Short code of the first `top_n` players of `players_window` that can be pasted in chat
+#[tauri::command]
+#[specta::specta]
+pub fn encode_share_code(players_window: PlayersWindow, top_n: u32) -> Result<String, String> {
+    encode_players_window(&players_window, top_n as usize)
+        .map_err(|e| format!("Failed to create share code: {e}"))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn decode_share_code(code: String) -> Result<PlayersWindow, String> {
+    decode_players_window(&code).map_err(|e| format!("Invalid share code: {e}"))
+}
+
+/// One command for any combination of stat kind, targets, attackers and time range.
+/// Returns the skills of `query.player_uid_str` if set, otherwise all players.
+#[tauri::command]
+#[specta::specta]
+pub fn query_encounter(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_state: tauri::State<'_, PlayerStateMutex>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    query: EncounterQuery,
+) -> Result<QueryResult, String> {
+    let meter_settings = meter_settings_state.lock().unwrap().clone();
+    let projection = {
+        let encounter = state.borrow().clone();
+        project_encounter(&encounter, &query, &meter_settings.target_filters)?
+    };
+    let player_state = player_state.lock().unwrap();
+    let player_cache = player_cache_state.lock().unwrap();
+    let stat_type = match query.stat_kind {
+        QueryStatKind::Dmg => StatType::Dmg,
+        QueryStatKind::Heal => StatType::Heal,
+    };
+    match &query.player_uid_str {
+        Some(player_uid_str) => {
+            let player_uid = player_uid_str
+                .parse()
+                .map_err(|e| format!("Invalid player uid {player_uid_str}: {e}"))?;
+            get_skill_window(
+                &projection,
+                player_uid,
+                stat_type,
+                &player_cache,
+                &player_state,
+                &meter_settings,
+            )
+            .map(|skills_window| QueryResult::Skills(Box::new(skills_window)))
+        }
+        None => Ok(QueryResult::Players(get_player_window(
+            &projection,
+            stat_type,
+            &player_cache,
+            &player_state,
+            &meter_settings,
+        ))),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_dps_timeline_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_uid_strs: Vec<String>,
+    smoothing_secs: Option<u32>,
+) -> Result<TimelineWindow, String> {
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_timeline_window(
+        &encounter,
+        &player_uid_strs,
+        smoothing_secs,
+        StatType::Dmg,
+        &player_cache,
+    )
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_dps_boss_only_timeline_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_uid_strs: Vec<String>,
+    smoothing_secs: Option<u32>,
+) -> Result<TimelineWindow, String> {
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_timeline_window(
+        &encounter,
+        &player_uid_strs,
+        smoothing_secs,
+        StatType::DmgBossOnly,
+        &player_cache,
+    )
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_heal_timeline_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    player_uid_strs: Vec<String>,
+    smoothing_secs: Option<u32>,
+) -> Result<TimelineWindow, String> {
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_timeline_window(
+        &encounter,
+        &player_uid_strs,
+        smoothing_secs,
+        StatType::Heal,
+        &player_cache,
+    )
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_dps_filtered_timeline_window(
+    state: tauri::State<'_, EncounterSnapshot>,
+    player_cache_state: tauri::State<'_, PlayerCacheMutex>,
+    filter_id: u32,
+    player_uid_strs: Vec<String>,
+    smoothing_secs: Option<u32>,
+) -> Result<TimelineWindow, String> {
+    let encounter = state.borrow().clone();
+    let player_cache = player_cache_state.lock().unwrap();
+    get_timeline_window(
+        &encounter,
+        &player_uid_strs,
+        smoothing_secs,
+        StatType::DmgFiltered(filter_id),
+        &player_cache,
+    )
+}
+
+/// Builds per-second series for the requested players (all players if empty).
+/// `smoothing_secs` applies a trailing rolling average over that many seconds.
+pub fn get_timeline_window(
+    encounter: &Encounter,
+    player_uid_strs: &[String],
+    smoothing_secs: Option<u32>,
+    stat_type: StatType,
+    player_cache: &crate::live::player_state::PlayerCache,
+) -> Result<TimelineWindow, String> {
+    let player_uids = player_uid_strs
+        .iter()
+        .map(|uid_str| {
+            uid_str
+                .parse::<i64>()
+                .map_err(|e| format!("Invalid player uid {uid_str}: {e}"))
+        })
+        .collect::<Result<Vec<i64>, String>>()?;
+
+    let mut entities = Vec::new();
+    if player_uids.is_empty() {
+        for (&entity_uid, entity) in &encounter.entity_uid_to_entity {
+            let timeline = get_entity_timeline(entity, stat_type);
+            if entity.entity_type == EEntityType::EntChar && !timeline.buckets.is_empty() {
+                entities.push((entity_uid, entity, timeline));
+            }
+        }
+    } else {
+        for player_uid in player_uids {
+            let Some(entity) = encounter.entity_uid_to_entity.get(&player_uid) else {
+                return Err(format!("Could not find player with uid {player_uid}"));
+            };
+            let timeline = get_entity_timeline(entity, stat_type);
+            entities.push((player_uid, entity, timeline));
+        }
+    }
+
+    // Series may have been compacted to different resolutions, so line them up on the coarsest
+    let bucket_ms = entities
+        .iter()
+        .map(|(_, _, timeline)| timeline.bucket_ms)
+        .max()
+        .unwrap_or(TIMELINE_BUCKET_MS);
+    let time_elapsed_ms = encounter.time_last_combat_packet_ms - encounter.time_fight_start_ms;
+    let len = if encounter.time_fight_start_ms == 0 {
+        0
+    } else {
+        (time_elapsed_ms / bucket_ms) as usize + 1
+    };
+    let bucket_secs = bucket_ms as f64 / 1000.0;
+    let smoothing_buckets = smoothing_secs
+        .map(|secs| (u128::from(secs) * 1000).div_ceil(bucket_ms).max(1) as usize)
+        .unwrap_or(1);
+
+    let mut timeline_window = TimelineWindow {
+        bucket_ms: bucket_ms as f64,
+        elapsed_ms: time_elapsed_ms as f64,
+        series: Vec::new(),
+    };
+    for (entity_uid, entity, timeline) in entities {
+        let buckets = timeline.resampled(bucket_ms, len);
+        let mut values_per_sec = Vec::with_capacity(len);
+        let mut window_sum = 0;
+        for (i, value) in buckets.iter().enumerate() {
+            window_sum += value;
+            if i >= smoothing_buckets {
+                window_sum -= buckets[i - smoothing_buckets];
+            }
+            let window_len = (i + 1).min(smoothing_buckets) as f64;
+            values_per_sec.push(nan_is_zero(window_sum as f64 / window_len / bucket_secs));
+        }
+        timeline_window.series.push(TimelineSeries {
+            uid: entity_uid as f64,
+            name: entity
+                .name
+                .clone()
+                .or_else(|| player_cache.get_name(entity_uid))
+                .unwrap_or_else(|| format!("Player {entity_uid}")),
+            class_name: class::get_class_name(
+                entity
+                    .class
+                    .or_else(|| player_cache.get_class(entity_uid))
+                    .unwrap_or(Class::Unknown),
+            )
+            .into(),
+            class_spec_name: class::get_class_spec(
+                entity
+                    .class_spec
+                    .or_else(|| player_cache.get_class_spec(entity_uid))
+                    .unwrap_or(ClassSpec::Unknown),
+            )
+            .into(),
+            values_per_sec,
+        });
+    }
+
+    Ok(timeline_window)
 }
 
 #[tauri::command]
@@ -405,8 +1296,8 @@ pub fn get_test_player_window() -> PlayersWindow {
             PlayerRow {
                 uid: 10000001.0,
                 name: "Name Stormblade (You)".to_string(),
-                class_name: "Stormblade".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Stormblade".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 100000.0,
                 value_per_sec: 10000.6,
@@ -415,14 +1306,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 2000.0,
+                lucky_hits: 0.0,
+                lucky_value: 1500.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000002.0,
                 name: "Name Frost Mage".to_string(),
-                class_name: "Frost Mage".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Frost Mage".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 90000.0,
                 value_per_sec: 6000.6,
@@ -431,14 +1332,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1800.0,
+                lucky_hits: 0.0,
+                lucky_value: 1350.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000003.0,
                 name: "Name Wind Knight".to_string(),
-                class_name: "Wind Knight".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Wind Knight".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 80000.0,
                 value_per_sec: 6000.6,
@@ -447,14 +1358,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1600.0,
+                lucky_hits: 0.0,
+                lucky_value: 1200.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000004.0,
                 name: "Name Verdant Oracle".to_string(),
-                class_name: "Verdant Oracle".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Verdant Oracle".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 70000.0,
                 value_per_sec: 6000.6,
@@ -463,14 +1384,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1400.0,
+                lucky_hits: 0.0,
+                lucky_value: 1050.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000005.0,
                 name: "Name Heavy Guardian".to_string(),
-                class_name: "Heavy Guardian".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Heavy Guardian".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 60000.0,
                 value_per_sec: 6000.6,
@@ -479,14 +1410,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1200.0,
+                lucky_hits: 0.0,
+                lucky_value: 900.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000006.0,
                 name: "Name Marksman".to_string(),
-                class_name: "Marksman".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Marksman".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 60000.0,
                 value_per_sec: 6000.6,
@@ -495,14 +1436,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1200.0,
+                lucky_hits: 0.0,
+                lucky_value: 900.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000007.0,
                 name: "Name Shield Knight".to_string(),
-                class_name: "Shield Knight".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Shield Knight".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 50000.0,
                 value_per_sec: 6000.6,
@@ -511,14 +1462,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 1000.0,
+                lucky_hits: 0.0,
+                lucky_value: 750.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000008.0,
                 name: "Name Beat Performer".to_string(),
-                class_name: "Beat Performer".to_string(),
-                class_spec_name: String::new(),
+                class_name: "Beat Performer".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 10000.0,
                 value_per_sec: 6000.6,
@@ -527,14 +1488,24 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 200.0,
+                lucky_hits: 0.0,
+                lucky_value: 150.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
             PlayerRow {
                 uid: 10000009.0,
                 name: "Blank Class".to_string(),
-                class_name: "blank".to_string(),
-                class_spec_name: String::new(),
+                class_name: "blank".into(),
+                class_spec_name: "".into(),
                 ability_score: 1500.0,
                 total_value: 10000.0,
                 value_per_sec: 6000.6,
@@ -543,8 +1514,18 @@ pub fn get_test_player_window() -> PlayersWindow {
                 crit_value_rate: 2.0,
                 lucky_rate: 0.10,
                 lucky_value_rate: 1.5,
+                crit_hits: 0.0,
+                crit_value: 200.0,
+                lucky_hits: 0.0,
+                lucky_value: 150.0,
                 hits: 200.0,
                 hits_per_minute: 3.3,
+                rolling_value_per_sec: 8000.0,
+                active_time_ms: 9000.0,
+                uptime_pct: 90.0,
+                active_value_per_sec: 11111.1,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
             },
         ],
         local_player_uid: 10000001.0,
@@ -562,6 +1543,77 @@ pub fn set_bptimer_enabled(state: tauri::State<BPTimerEnabledMutex>, enabled: bo
     );
 }
 
+#[tauri::command]
+#[specta::specta]
+pub async fn set_local_api_config(app_handle: tauri::AppHandle, config: LocalApiConfig) {
+    local_api::restart(&app_handle, config).await;
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_webhook_config(state: tauri::State<'_, WebhookConfigMutex>, config: WebhookConfig) {
+    *state.lock().unwrap() = config;
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_rolling_window_secs(state: tauri::State<'_, MeterSettingsMutex>, secs: u32) {
+    let mut meter_settings = state.lock().unwrap();
+    meter_settings.set_rolling_window_secs(secs);
+    info!(
+        "rolling window set to {}s via settings",
+        meter_settings.rolling_window_secs
+    );
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_live_update_interval_ms(state: tauri::State<'_, MeterSettingsMutex>, ms: u32) {
+    let mut meter_settings = state.lock().unwrap();
+    meter_settings.set_live_update_interval_ms(ms);
+    info!(
+        "live updates set to every {}ms via settings",
+        meter_settings.live_update_interval_ms
+    );
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_encounter_idle_timeout_secs(state: tauri::State<'_, MeterSettingsMutex>, secs: u32) {
+    let mut meter_settings = state.lock().unwrap();
+    meter_settings.encounter_idle_timeout_secs = secs;
+    info!("encounter idle timeout set to {secs}s via settings");
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_fight_start_trigger(
+    state: tauri::State<'_, MeterSettingsMutex>,
+    trigger: FightStartTrigger,
+) {
+    let mut meter_settings = state.lock().unwrap();
+    meter_settings.fight_start_trigger = trigger;
+    info!("fight start trigger set to {trigger:?} via settings");
+}
+
+/// The current encounter is recomputed from its combat log, so new filters cover the whole fight
+#[tauri::command]
+#[specta::specta]
+pub fn set_target_filters(
+    control_state: tauri::State<'_, EncounterControlSender>,
+    meter_settings_state: tauri::State<'_, MeterSettingsMutex>,
+    target_filters: Vec<TargetFilter>,
+) {
+    let mut meter_settings = meter_settings_state.lock().unwrap();
+    info!("{} target filters set via settings", target_filters.len());
+    let target_filters = Arc::<[TargetFilter]>::from(target_filters);
+    meter_settings.target_filters = target_filters.clone();
+    send_encounter_control(
+        &control_state,
+        EncounterControl::SetTargetFilters(target_filters),
+    );
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String> {
@@ -569,8 +1621,8 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
         inspected_player: PlayerRow {
             uid: 10000001.0,
             name: "Name Stormblade".to_string(),
-            class_name: "Stormblade".to_string(),
-            class_spec_name: "Iaido".to_string(),
+            class_name: "Stormblade".into(),
+            class_spec_name: "Iaido".into(),
             ability_score: 1500.0,
             total_value: 100000.0,
             value_per_sec: 10000.6,
@@ -579,13 +1631,23 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
             crit_value_rate: 2.0,
             lucky_rate: 0.10,
             lucky_value_rate: 1.5,
+            crit_hits: 0.0,
+            crit_value: 2000.0,
+            lucky_hits: 0.0,
+            lucky_value: 1500.0,
             hits: 200.0,
             hits_per_minute: 3.3,
+            rolling_value_per_sec: 8000.0,
+            active_time_ms: 9000.0,
+            uptime_pct: 90.0,
+            active_value_per_sec: 11111.1,
+            crit_multiplier: 1.5,
+            lucky_multiplier: 1.3,
         },
         skill_rows: vec![
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 1".to_string(),
+                name: "Skill 1".into(),
                 total_value: 100000.0,
                 value_per_sec: 5000.0,
                 value_pct: 80.0,
@@ -593,12 +1655,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 2.1,
                 lucky_rate: 0.12,
                 lucky_value_rate: 1.4,
+                crit_hits: 0.0,
+                crit_value: 2100.0,
+                lucky_hits: 0.0,
+                lucky_value: 1400.0,
                 hits: 80.0,
                 hits_per_minute: 1.5,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 2".to_string(),
+                name: "Skill 2".into(),
                 total_value: 50000.0,
                 value_per_sec: 7345.6,
                 value_pct: 70.0,
@@ -606,12 +1678,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 950.0,
+                lucky_hits: 0.0,
+                lucky_value: 650.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 3".to_string(),
+                name: "Skill 3".into(),
                 total_value: 33000.0,
                 value_per_sec: 7345.6,
                 value_pct: 60.0,
@@ -619,12 +1701,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 627.0,
+                lucky_hits: 0.0,
+                lucky_value: 429.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 4".to_string(),
+                name: "Skill 4".into(),
                 total_value: 23000.0,
                 value_per_sec: 7345.6,
                 value_pct: 50.0,
@@ -632,12 +1724,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 437.0,
+                lucky_hits: 0.0,
+                lucky_value: 299.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 5".to_string(),
+                name: "Skill 5".into(),
                 total_value: 11000.0,
                 value_per_sec: 7345.6,
                 value_pct: 40.0,
@@ -645,12 +1747,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 209.0,
+                lucky_hits: 0.0,
+                lucky_value: 143.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 6".to_string(),
+                name: "Skill 6".into(),
                 total_value: 1000.0,
                 value_per_sec: 7345.6,
                 value_pct: 30.0,
@@ -658,12 +1770,22 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 19.0,
+                lucky_hits: 0.0,
+                lucky_value: 13.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
             SkillRow {
                 uid: 3602.0,
-                name: "Skill 7".to_string(),
+                name: "Skill 7".into(),
                 total_value: 400.0,
                 value_per_sec: 7345.6,
                 value_pct: 20.0,
@@ -671,8 +1793,18 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
                 crit_value_rate: 1.9,
                 lucky_rate: 0.08,
                 lucky_value_rate: 1.3,
+                crit_hits: 0.0,
+                crit_value: 8.0,
+                lucky_hits: 0.0,
+                lucky_value: 5.0,
                 hits: 120.0,
                 hits_per_minute: 1.8,
+                crit_multiplier: 1.5,
+                lucky_multiplier: 1.3,
+                normal_hit_dist: HitDistributionRow::default(),
+                crit_hit_dist: HitDistributionRow::default(),
+                lucky_hit_dist: HitDistributionRow::default(),
+                crit_lucky_hit_dist: HitDistributionRow::default(),
             },
         ],
         local_player_uid: 10000001.0,
@@ -683,9 +1815,9 @@ pub fn get_test_skill_window(_player_uid: String) -> Result<SkillsWindow, String
 #[tauri::command]
 #[specta::specta]
 pub fn extract_modules_from_local_player(
-    state: tauri::State<'_, EncounterMutex>,
+    state: tauri::State<'_, EncounterSnapshot>,
 ) -> Result<ModuleOptimizerExportResult, String> {
-    let encounter = state.lock().unwrap();
+    let encounter = state.borrow().clone();
     let Some(local_player) = &encounter.local_player else {
         return Err("No local player data available".to_string());
     };
@@ -729,3 +1861,72 @@ pub struct ModuleOptimizerExportResult {
     pub url: String,
     pub exported_path: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::live::combat_log::{CombatEvent, record};
+    use crate::live::commands::{PlayerWindowCache, StatType};
+    use crate::live::meter_settings::MeterSettings;
+    use crate::live::opcodes_models::{Encounter, Entity};
+    use crate::live::player_state::{PlayerCache, PlayerState};
+    use crate::protocol::pb::EEntityType;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_player_window_cache() {
+        let mut encounter = Encounter {
+            time_fight_start_ms: 1000,
+            ..Default::default()
+        };
+        for uid in [1, 2] {
+            let entity = Entity {
+                entity_type: EEntityType::EntChar,
+                name: Some(format!("Player {uid}")),
+                ..Default::default()
+            };
+            encounter.entity_uid_to_entity.insert(uid, Arc::new(entity));
+        }
+        let hit = |encounter: &mut Encounter, attacker_uid, timestamp_ms, value| {
+            let combat_event = CombatEvent {
+                timestamp_ms,
+                attacker_uid,
+                target_uid: 100,
+                skill_uid: 1,
+                value,
+                ..Default::default()
+            };
+            record(encounter, &combat_event, &[]);
+        };
+        let update = |player_window_cache: &mut PlayerWindowCache, encounter: &Encounter| {
+            player_window_cache.update(
+                encounter,
+                StatType::Dmg,
+                &PlayerCache::default(),
+                &PlayerState::default(),
+                &MeterSettings::default(),
+            );
+        };
+        let mut player_window_cache = PlayerWindowCache::default();
+
+        hit(&mut encounter, 1, 1000, 300);
+        hit(&mut encounter, 2, 2000, 100);
+        update(&mut player_window_cache, &encounter);
+        assert!(player_window_cache.is_reordered);
+        assert_eq!(player_window_cache.changed_row_indices, [0, 1]);
+
+        update(&mut player_window_cache, &encounter);
+        assert!(!player_window_cache.is_changed());
+
+        // Player 2 takes the lead, player 1 only gets a lower DPS from the longer fight
+        hit(&mut encounter, 2, 3000, 400);
+        update(&mut player_window_cache, &encounter);
+        assert!(player_window_cache.is_reordered);
+        assert_eq!(player_window_cache.changed_row_indices, [0, 1]);
+        let player_rows = &player_window_cache.window.player_rows;
+        assert_eq!(player_rows[0].uid, 2.0);
+        assert_eq!(player_rows[0].total_value, 500.0);
+        assert_eq!(player_rows[1].name, "Player 1");
+        assert_eq!(player_rows[1].value_per_sec, 150.0);
+        assert_eq!(player_window_cache.window.top_value, 500.0);
+    }
+}
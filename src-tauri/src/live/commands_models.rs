@@ -2,16 +2,22 @@
 f64 is used in the models even when it doesn't make sense due to limitations with serde serializing u128 as a JSON number instead of a string
 */
 
-#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+use crate::live::encounter_history::EncounterEndReason;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct HeaderInfo {
     pub total_dps: f64,
     pub total_dmg: f64,
     pub elapsed_ms: f64,
     pub time_last_combat_packet_ms: f64,
+    pub rolling_dps: f64,
+    pub rolling_window_secs: f64,
 }
 
-#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayersWindow {
     pub player_rows: PlayerRows,
@@ -21,13 +27,13 @@ pub struct PlayersWindow {
 
 pub type PlayerRows = Vec<PlayerRow>;
 
-#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerRow {
     pub uid: f64,
     pub ability_score: f64,
-    pub class_name: String,
-    pub class_spec_name: String,
+    pub class_name: Cow<'static, str>,
+    pub class_spec_name: Cow<'static, str>,
     pub name: String,
     // Stats
     pub total_value: f64,
@@ -37,8 +43,19 @@ pub struct PlayerRow {
     pub crit_value_rate: f64,
     pub lucky_rate: f64,
     pub lucky_value_rate: f64,
+    // Exact counts, exports can't get them back from the rounded rates
+    pub crit_hits: f64,
+    pub crit_value: f64,
+    pub lucky_hits: f64,
+    pub lucky_value: f64,
     pub hits: f64,
     pub hits_per_minute: f64,
+    pub rolling_value_per_sec: f64,
+    pub active_time_ms: f64,
+    pub uptime_pct: f64,
+    pub active_value_per_sec: f64,
+    pub crit_multiplier: f64,
+    pub lucky_multiplier: f64,
 }
 
 #[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
@@ -56,7 +73,7 @@ pub type SkillRows = Vec<SkillRow>;
 #[serde(rename_all = "camelCase")]
 pub struct SkillRow {
     pub uid: f64,
-    pub name: String,
+    pub name: Cow<'static, str>,
     // Stats
     pub total_value: f64,
     pub value_per_sec: f64,
@@ -65,6 +82,156 @@ pub struct SkillRow {
     pub crit_value_rate: f64,
     pub lucky_rate: f64,
     pub lucky_value_rate: f64,
+    // Exact counts, exports can't get them back from the rounded rates
+    pub crit_hits: f64,
+    pub crit_value: f64,
+    pub lucky_hits: f64,
+    pub lucky_value: f64,
     pub hits: f64,
     pub hits_per_minute: f64,
+    pub crit_multiplier: f64,
+    pub lucky_multiplier: f64,
+    pub normal_hit_dist: HitDistributionRow,
+    pub crit_hit_dist: HitDistributionRow,
+    pub lucky_hit_dist: HitDistributionRow,
+    pub crit_lucky_hit_dist: HitDistributionRow,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HitDistributionRow {
+    pub hits: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineWindow {
+    pub bucket_ms: f64,
+    pub elapsed_ms: f64,
+    pub series: TimelineSeriesList,
+}
+
+pub type TimelineSeriesList = Vec<TimelineSeries>;
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineSeries {
+    pub uid: f64,
+    pub name: String,
+    pub class_name: Cow<'static, str>,
+    pub class_spec_name: Cow<'static, str>,
+    pub values_per_sec: Vec<f64>,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedEncounter {
+    pub id: String,
+    pub name: String,
+    pub end_reason: Option<EncounterEndReason>, // None while the encounter is still running
+    pub time_fight_start_ms: f64,
+    pub time_last_combat_packet_ms: f64,
+    pub header_info: HeaderInfo,
+    pub dps_players: PlayersWindow,
+    pub dps_boss_only_players: PlayersWindow,
+    pub heal_players: PlayersWindow,
+    // Keyed by player uid, same as the uid strings passed to the skill window commands
+    pub dps_skills: HashMap<String, SkillsWindow>,
+    pub dps_boss_only_skills: HashMap<String, SkillsWindow>,
+    pub heal_skills: HashMap<String, SkillsWindow>,
+    // Added later, missing in older history files
+    #[serde(default)]
+    pub line_id: Option<u32>,
+    #[serde(default)]
+    pub level_map_id: Option<u32>,
+    #[serde(default)]
+    pub entities: Vec<SavedEntity>,
+}
+
+/// Every entity that dealt, healed or took something in a saved encounter, most damage taken first
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedEntity {
+    pub uid: f64,
+    pub entity_type: Cow<'static, str>, // EEntityType name, e.g. EntChar or EntMonster
+    pub name: Option<String>,
+    pub monster_id: Option<u32>,
+    pub is_boss: bool,
+    pub max_hp: Option<f64>,
+    pub dmg_taken: f64,
+    pub deaths: u32,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedEncounterSummary {
+    pub id: String,
+    pub name: String,
+    pub end_reason: Option<EncounterEndReason>,
+    pub time_fight_start_ms: f64,
+    pub elapsed_ms: f64,
+    pub total_dmg: f64,
+    pub player_count: f64,
+}
+
+impl From<&SavedEncounter> for SavedEncounterSummary {
+    fn from(saved_encounter: &SavedEncounter) -> Self {
+        Self {
+            id: saved_encounter.id.clone(),
+            name: saved_encounter.name.clone(),
+            end_reason: saved_encounter.end_reason,
+            time_fight_start_ms: saved_encounter.time_fight_start_ms,
+            elapsed_ms: saved_encounter.header_info.elapsed_ms,
+            total_dmg: saved_encounter.header_info.total_dmg,
+            player_count: saved_encounter.dps_players.player_rows.len() as f64,
+        }
+    }
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetSnapshotSummary {
+    pub index: f64,
+    pub time_fight_start_ms: f64,
+    pub elapsed_ms: f64,
+    pub total_dmg: f64,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryStatKind {
+    Dmg,
+    Heal,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum QueryTargets {
+    All,
+    Bosses,
+    Filter(u32), // TargetFilter::id
+    MonsterIds(Vec<u32>),
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncounterQuery {
+    pub stat_kind: QueryStatKind,
+    pub targets: QueryTargets,
+    pub attacker_uid_strs: Vec<String>, // empty = all players
+    // Time range relative to the fight start
+    pub start_ms: Option<f64>,
+    pub end_ms: Option<f64>,
+    pub merge_summons: bool, // false = summons get their own row instead of counting for the owner
+    pub player_uid_str: Option<String>, // set to get this player's skills instead of all players
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum QueryResult {
+    Players(PlayersWindow),
+    Skills(Box<SkillsWindow>),
 }
@@ -0,0 +1,556 @@
+use crate::live::commands_models::{
+    PlayerRow, PlayersWindow, SavedEncounter, SkillRows, SkillsWindow,
+};
+use crate::live::encounter_history::EncounterEndReason;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Bumped whenever a field is renamed, removed or changes meaning.
+/// Fields may be added within a version, so readers should ignore unknown ones.
+pub const ENCOUNTER_EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Root of the files written by `export_encounter`.
+///
+/// All numbers are JSON numbers, times are unix milliseconds unless the name says otherwise
+/// and uids are the game's entity uids. Player and skill stats use the same fields as the
+/// meter windows (`PlayerRow` and `SkillRow` in `src/lib/bindings.ts`).
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncounterExport {
+    pub schema_version: u32,
+    pub app_version: String,
+    pub exported_at_ms: f64,
+    pub encounter: ExportedEncounterInfo,
+    pub entities: Vec<ExportedEntity>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedEncounterInfo {
+    pub id: String,
+    pub name: String,
+    pub end_reason: Option<EncounterEndReason>, // None while the encounter is still running
+    pub time_fight_start_ms: f64,
+    pub time_last_combat_packet_ms: f64,
+    pub elapsed_ms: f64,
+    pub total_dmg: f64,
+    pub total_dps: f64,
+    pub line_id: Option<u32>,
+    pub level_map_id: Option<u32>,
+}
+
+/// A player, monster or other entity that took part in the encounter
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedEntity {
+    pub uid: f64,
+    pub entity_type: Cow<'static, str>, // EntChar for players, EntMonster for monsters
+    pub name: Option<String>,
+    // Players
+    pub class_name: Option<Cow<'static, str>>,
+    pub class_spec_name: Option<Cow<'static, str>>,
+    pub ability_score: Option<f64>,
+    // Monsters
+    pub monster_id: Option<u32>,
+    pub is_boss: bool,
+    pub max_hp: Option<f64>,
+    // Stats
+    pub dmg_taken: f64,
+    pub deaths: u32,
+    pub dmg: Option<ExportedStats>, // damage to all targets
+    pub dmg_boss_only: Option<ExportedStats>,
+    pub heal: Option<ExportedStats>,
+}
+
+/// Totals of one entity and the breakdown per skill, most damage or healing first
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedStats {
+    pub total: PlayerRow,
+    pub skills: SkillRows,
+}
+
+pub fn get_encounter_export(saved_encounter: &SavedEncounter) -> EncounterExport {
+    let player_rows = [
+        &saved_encounter.dps_players,
+        &saved_encounter.dps_boss_only_players,
+        &saved_encounter.heal_players,
+    ]
+    .into_iter()
+    .flat_map(|players_window| players_window.player_rows.iter())
+    .map(|player_row| (player_row.uid.to_string(), player_row))
+    .collect::<HashMap<_, _>>();
+    let get_stats = |skill_windows: &HashMap<String, SkillsWindow>, uid_str: &str| {
+        skill_windows
+            .get(uid_str)
+            .map(|skills_window| ExportedStats {
+                total: skills_window.inspected_player.clone(),
+                skills: skills_window.skill_rows.clone(),
+            })
+    };
+
+    let entities = saved_encounter
+        .entities
+        .iter()
+        .map(|saved_entity| {
+            let uid_str = saved_entity.uid.to_string();
+            let player_row = player_rows.get(&uid_str);
+            ExportedEntity {
+                uid: saved_entity.uid,
+                entity_type: saved_entity.entity_type.clone(),
+                name: saved_entity
+                    .name
+                    .clone()
+                    .or_else(|| player_row.map(|player_row| player_row.name.clone())),
+                class_name: player_row.map(|player_row| player_row.class_name.clone()),
+                class_spec_name: player_row.map(|player_row| player_row.class_spec_name.clone()),
+                ability_score: player_row.map(|player_row| player_row.ability_score),
+                monster_id: saved_entity.monster_id,
+                is_boss: saved_entity.is_boss,
+                max_hp: saved_entity.max_hp,
+                dmg_taken: saved_entity.dmg_taken,
+                deaths: saved_entity.deaths,
+                dmg: get_stats(&saved_encounter.dps_skills, &uid_str),
+                dmg_boss_only: get_stats(&saved_encounter.dps_boss_only_skills, &uid_str),
+                heal: get_stats(&saved_encounter.heal_skills, &uid_str),
+            }
+        })
+        .collect();
+
+    EncounterExport {
+        schema_version: ENCOUNTER_EXPORT_SCHEMA_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at_ms: chrono::Utc::now().timestamp_millis() as f64,
+        encounter: ExportedEncounterInfo {
+            id: saved_encounter.id.clone(),
+            name: saved_encounter.name.clone(),
+            end_reason: saved_encounter.end_reason,
+            time_fight_start_ms: saved_encounter.time_fight_start_ms,
+            time_last_combat_packet_ms: saved_encounter.time_last_combat_packet_ms,
+            elapsed_ms: saved_encounter.header_info.elapsed_ms,
+            total_dmg: saved_encounter.header_info.total_dmg,
+            total_dps: saved_encounter.header_info.total_dps,
+            line_id: saved_encounter.line_id,
+            level_map_id: saved_encounter.level_map_id,
+        },
+        entities,
+    }
+}
+
+/// Save an encounter export to a JSON file in the user's Downloads folder.
+/// Returns the file path on success.
+pub fn save_encounter_export_to_file(
+    encounter_export: &EncounterExport,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    use std::fs;
+    use std::io::Write;
+
+    let dir = dirs::download_dir().ok_or("Could not find Downloads directory")?;
+    let filename = format!(
+        "bpsr-logs-encounter-{}-{}.json",
+        encounter_export.encounter.id,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = dir.join(filename);
+
+    let json = serde_json::to_string_pretty(encounter_export)?;
+    let mut f = fs::File::create(&path)?;
+    f.write_all(json.as_bytes())?;
+    f.sync_all()?;
+
+    Ok(path)
+}
+
+const PLAYER_CSV_COLUMNS: &[&str] = &[
+    "uid",
+    "name",
+    "className",
+    "classSpecName",
+    "abilityScore",
+    "totalValue",
+    "valuePerSec",
+    "valuePct",
+    "critRate",
+    "critValueRate",
+    "luckyRate",
+    "luckyValueRate",
+    "hits",
+    "hitsPerMinute",
+    "rollingValuePerSec",
+    "activeTimeMs",
+    "uptimePct",
+    "activeValuePerSec",
+    "critMultiplier",
+    "luckyMultiplier",
+    "critHits",
+    "critValue",
+    "luckyHits",
+    "luckyValue",
+];
+
+const SKILL_CSV_COLUMNS: &[&str] = &[
+    "playerUid",
+    "playerName",
+    "uid",
+    "name",
+    "totalValue",
+    "valuePerSec",
+    "valuePct",
+    "critRate",
+    "critValueRate",
+    "luckyRate",
+    "luckyValueRate",
+    "hits",
+    "hitsPerMinute",
+    "critMultiplier",
+    "luckyMultiplier",
+    "critHits",
+    "critValue",
+    "luckyHits",
+    "luckyValue",
+    "normalHits",
+    "normalMin",
+    "normalMax",
+    "normalMean",
+    "normalP50",
+    "normalP90",
+    "normalP99",
+    "critHitsDist",
+    "critMin",
+    "critMax",
+    "critMean",
+    "critP50",
+    "critP90",
+    "critP99",
+    "luckyHitsDist",
+    "luckyMin",
+    "luckyMax",
+    "luckyMean",
+    "luckyP50",
+    "luckyP90",
+    "luckyP99",
+    "critLuckyHitsDist",
+    "critLuckyMin",
+    "critLuckyMax",
+    "critLuckyMean",
+    "critLuckyP50",
+    "critLuckyP90",
+    "critLuckyP99",
+];
+
+/// Player table of one mode, same order as the meter window
+pub fn get_players_csv(players_window: &PlayersWindow) -> String {
+    let mut csv = String::new();
+    push_csv_record(
+        &mut csv,
+        PLAYER_CSV_COLUMNS.iter().map(|&column| column.into()),
+    );
+    for player_row in &players_window.player_rows {
+        let mut record = vec![
+            player_row.uid.to_string(),
+            player_row.name.clone(),
+            player_row.class_name.to_string(),
+            player_row.class_spec_name.to_string(),
+        ];
+        record.extend(
+            [
+                player_row.ability_score,
+                player_row.total_value,
+                player_row.value_per_sec,
+                player_row.value_pct,
+                player_row.crit_rate,
+                player_row.crit_value_rate,
+                player_row.lucky_rate,
+                player_row.lucky_value_rate,
+                player_row.hits,
+                player_row.hits_per_minute,
+                player_row.rolling_value_per_sec,
+                player_row.active_time_ms,
+                player_row.uptime_pct,
+                player_row.active_value_per_sec,
+                player_row.crit_multiplier,
+                player_row.lucky_multiplier,
+                player_row.crit_hits,
+                player_row.crit_value,
+                player_row.lucky_hits,
+                player_row.lucky_value,
+            ]
+            .map(|value| value.to_string()),
+        );
+        push_csv_record(&mut csv, record);
+    }
+    csv
+}
+
+/// Skill tables of every player of one mode in a single file, players in meter window order
+pub fn get_skills_csv(
+    players_window: &PlayersWindow,
+    skill_windows: &HashMap<String, SkillsWindow>,
+) -> String {
+    let mut csv = String::new();
+    push_csv_record(
+        &mut csv,
+        SKILL_CSV_COLUMNS.iter().map(|&column| column.into()),
+    );
+    for player_row in &players_window.player_rows {
+        let Some(skills_window) = skill_windows.get(&player_row.uid.to_string()) else {
+            continue;
+        };
+        for skill_row in &skills_window.skill_rows {
+            let mut record = vec![
+                player_row.uid.to_string(),
+                player_row.name.clone(),
+                skill_row.uid.to_string(),
+                skill_row.name.to_string(),
+            ];
+            record.extend(
+                [
+                    skill_row.total_value,
+                    skill_row.value_per_sec,
+                    skill_row.value_pct,
+                    skill_row.crit_rate,
+                    skill_row.crit_value_rate,
+                    skill_row.lucky_rate,
+                    skill_row.lucky_value_rate,
+                    skill_row.hits,
+                    skill_row.hits_per_minute,
+                    skill_row.crit_multiplier,
+                    skill_row.lucky_multiplier,
+                    skill_row.crit_hits,
+                    skill_row.crit_value,
+                    skill_row.lucky_hits,
+                    skill_row.lucky_value,
+                ]
+                .map(|value| value.to_string()),
+            );
+            for hit_dist in [
+                &skill_row.normal_hit_dist,
+                &skill_row.crit_hit_dist,
+                &skill_row.lucky_hit_dist,
+                &skill_row.crit_lucky_hit_dist,
+            ] {
+                record.extend(
+                    [
+                        hit_dist.hits,
+                        hit_dist.min,
+                        hit_dist.max,
+                        hit_dist.mean,
+                        hit_dist.p50,
+                        hit_dist.p90,
+                        hit_dist.p99,
+                    ]
+                    .map(|value| value.to_string()),
+                );
+            }
+            push_csv_record(&mut csv, record);
+        }
+    }
+    csv
+}
+
+fn push_csv_record(csv: &mut String, fields: impl IntoIterator<Item = String>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        // Spreadsheets run text starting with these as formulas, e.g. a player named "=cmd|...".
+        // Numbers like -1 are left alone.
+        let field = if field.starts_with(['=', '+', '-', '@']) && field.parse::<f64>().is_err() {
+            format!("'{field}")
+        } else {
+            field
+        };
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(&field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+/// Save the player and skill tables of every mode as CSV files in a new folder in the
+/// user's Downloads folder. Returns the folder path on success.
+pub fn save_encounter_csv_to_dir(
+    saved_encounter: &SavedEncounter,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    use std::fs;
+    use std::io::Write;
+
+    let dir = dirs::download_dir().ok_or("Could not find Downloads directory")?;
+    let dir = dir.join(format!(
+        "bpsr-logs-encounter-{}-{}",
+        saved_encounter.id,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::create_dir_all(&dir)?;
+
+    let modes = [
+        (
+            "dmg",
+            &saved_encounter.dps_players,
+            &saved_encounter.dps_skills,
+        ),
+        (
+            "dmg-boss-only",
+            &saved_encounter.dps_boss_only_players,
+            &saved_encounter.dps_boss_only_skills,
+        ),
+        (
+            "heal",
+            &saved_encounter.heal_players,
+            &saved_encounter.heal_skills,
+        ),
+    ];
+    for (mode, players_window, skill_windows) in modes {
+        let tables = [
+            (
+                format!("players-{mode}.csv"),
+                get_players_csv(players_window),
+            ),
+            (
+                format!("skills-{mode}.csv"),
+                get_skills_csv(players_window, skill_windows),
+            ),
+        ];
+        for (filename, csv) in tables {
+            let mut f = fs::File::create(dir.join(filename))?;
+            // BOM so Excel reads names as UTF-8
+            f.write_all("\u{feff}".as_bytes())?;
+            f.write_all(csv.as_bytes())?;
+            f.sync_all()?;
+        }
+    }
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::commands_models::{
+        HeaderInfo, PlayerRow, PlayersWindow, SavedEncounter, SavedEntity, SkillRow, SkillsWindow,
+    };
+    use crate::live::encounter_export::{get_encounter_export, get_players_csv, get_skills_csv};
+    use crate::live::encounter_history::EncounterEndReason;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encounter_export() {
+        let player_row = PlayerRow {
+            uid: 42.0,
+            name: "Player".to_string(),
+            class_name: "Stormblade".into(),
+            total_value: 1000.0,
+            ..Default::default()
+        };
+        let skills_window = SkillsWindow {
+            inspected_player: player_row.clone(),
+            skill_rows: vec![SkillRow {
+                uid: 1.0,
+                total_value: 1000.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut saved_encounter = SavedEncounter {
+            id: "1".to_string(),
+            name: "Encounter".to_string(),
+            end_reason: None,
+            time_fight_start_ms: 1.0,
+            time_last_combat_packet_ms: 2.0,
+            header_info: HeaderInfo::default(),
+            dps_players: PlayersWindow {
+                player_rows: vec![player_row],
+                ..Default::default()
+            },
+            dps_boss_only_players: PlayersWindow::default(),
+            heal_players: PlayersWindow::default(),
+            dps_skills: HashMap::from([("42".to_string(), skills_window)]),
+            dps_boss_only_skills: HashMap::new(),
+            heal_skills: HashMap::new(),
+            line_id: Some(3),
+            level_map_id: Some(4),
+            entities: vec![
+                SavedEntity {
+                    uid: 7.0,
+                    entity_type: "EntMonster".into(),
+                    monster_id: Some(100),
+                    dmg_taken: 1000.0,
+                    ..Default::default()
+                },
+                SavedEntity {
+                    uid: 42.0,
+                    entity_type: "EntChar".into(),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let encounter_export = get_encounter_export(&saved_encounter);
+        assert_eq!(encounter_export.encounter.end_reason, None);
+        assert_eq!(encounter_export.encounter.line_id, Some(3));
+        let [monster, player] = encounter_export.entities.as_slice() else {
+            panic!("expected two entities");
+        };
+        assert!(monster.dmg.is_none() && monster.class_name.is_none());
+        assert_eq!(player.name.as_deref(), Some("Player"));
+        assert_eq!(player.class_name.as_deref(), Some("Stormblade"));
+        assert_eq!(player.dmg.as_ref().map(|dmg| dmg.skills.len()), Some(1));
+        assert!(player.heal.is_none());
+
+        saved_encounter.end_reason = Some(EncounterEndReason::BossDefeated);
+        let encounter_export = get_encounter_export(&saved_encounter);
+        assert_eq!(
+            encounter_export.encounter.end_reason,
+            Some(EncounterEndReason::BossDefeated)
+        );
+    }
+
+    #[test]
+    fn test_encounter_csv() {
+        let players_window = PlayersWindow {
+            player_rows: vec![PlayerRow {
+                uid: 42.0,
+                name: "Comma, \"Quote\"".to_string(),
+                total_value: 1000.0,
+                ability_score: -1.0,
+                hits: 3.0,
+                crit_rate: 100.0 / 3.0,
+                crit_hits: 1.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let skill_windows = HashMap::from([(
+            "42".to_string(),
+            SkillsWindow {
+                skill_rows: vec![
+                    SkillRow {
+                        name: "=1+1".into(),
+                        ..Default::default()
+                    },
+                    SkillRow::default(),
+                ],
+                ..Default::default()
+            },
+        )]);
+
+        let players_csv = get_players_csv(&players_window);
+        let lines = players_csv.split_terminator("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("42,\"Comma, \"\"Quote\"\"\",,,-1,1000,"));
+        assert!(lines[1].ends_with(",1,0,0,0"));
+
+        let skills_csv = get_skills_csv(&players_window, &skill_windows);
+        let lines = skills_csv.split_terminator("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",0,'=1+1,"));
+        assert_eq!(
+            lines[0].split(',').count(),
+            lines[2].split(',').count() - 1 // the player name has a comma
+        );
+    }
+}
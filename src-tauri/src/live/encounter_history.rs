@@ -0,0 +1,403 @@
+use crate::live::commands::get_saved_encounter;
+use crate::live::commands_models::{SavedEncounter, SavedEncounterSummary};
+use crate::live::meter_settings::MeterSettingsMutex;
+use crate::live::opcodes_models::Encounter;
+use crate::live::player_state::{PlayerCacheMutex, PlayerState};
+use crate::live::webhook::{WebhookConfigMutex, get_encounter_summary, send_encounter_summary};
+use log::{info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncounterEndReason {
+    IdleTimeout,
+    BossDefeated,
+    Reset,
+    ServerChange,
+    LineChange,
+}
+
+// Oldest encounters are deleted once the history grows past this
+pub const MAX_SAVED_ENCOUNTERS: usize = 500;
+// Summaries of all saved encounters, so listing them doesn't parse every file
+const INDEX_FILE_STEM: &str = "index";
+
+/// Writes to a temp file first and renames it into place, so a crash never leaves a half
+/// written file behind
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut f = fs::File::create(&tmp_path)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+    drop(f);
+    fs::rename(&tmp_path, path)
+}
+
+/// Finished encounters stored as one JSON file each in the app data dir
+#[derive(Debug, Default)]
+pub struct EncounterHistory {
+    dir: Option<PathBuf>,
+}
+
+impl EncounterHistory {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    fn get_path(&self, id: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("Encounter history is not available")?;
+        // ids are generated by us, anything else could escape the history dir
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!("Invalid encounter id {id}").into());
+        }
+        Ok(dir.join(format!("{id}.json")))
+    }
+
+    pub fn save(&self, saved_encounter: &SavedEncounter) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.get_path(&saved_encounter.id)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(saved_encounter)?;
+        write_atomic(&path, json.as_bytes())?;
+        let summary = SavedEncounterSummary::from(saved_encounter);
+        self.update_index(|summaries| {
+            summaries.retain(|other| other.id != summary.id);
+            summaries.push(summary);
+        })
+    }
+
+    pub fn load(&self, id: &str) -> Result<SavedEncounter, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(self.get_path(id)?)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Summaries of all stored encounters, newest first. They come from the index, only files
+    /// missing from it are parsed, e.g. ones saved by an older version.
+    pub fn list(&self) -> Result<Vec<SavedEncounterSummary>, Box<dyn std::error::Error>> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("Encounter history is not available")?;
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Ok(Vec::new()); // nothing saved yet
+        };
+
+        let mut indexed = self
+            .read_index()
+            .into_iter()
+            .map(|summary| (summary.id.clone(), summary))
+            .collect::<HashMap<_, _>>();
+        let mut summaries = Vec::new();
+        let mut is_index_stale = false;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if id == INDEX_FILE_STEM {
+                continue;
+            }
+            if let Some(summary) = indexed.remove(id) {
+                summaries.push(summary);
+                continue;
+            }
+            is_index_stale = true;
+            match self.load(id) {
+                Ok(saved_encounter) => {
+                    summaries.push(SavedEncounterSummary::from(&saved_encounter))
+                }
+                Err(e) => warn!("Skipping unreadable encounter {}: {e}", path.display()),
+            }
+        }
+        // entries left over in the index belong to files that are gone
+        if is_index_stale || !indexed.is_empty() {
+            if let Err(e) = self.write_index(&summaries) {
+                warn!("Failed to update the encounter index: {e}");
+            }
+        }
+        summaries.sort_by(|this, other| {
+            other
+                .time_fight_start_ms
+                .partial_cmp(&this.time_fight_start_ms)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(summaries)
+    }
+
+    pub fn rename(&self, id: &str, name: String) -> Result<(), Box<dyn std::error::Error>> {
+        let mut saved_encounter = self.load(id)?;
+        saved_encounter.name = name;
+        self.save(&saved_encounter)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::remove_file(self.get_path(id)?)?;
+        self.update_index(|summaries| summaries.retain(|summary| summary.id != id))
+    }
+
+    fn get_index_path(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("Encounter history is not available")?;
+        Ok(dir.join(format!("{INDEX_FILE_STEM}.json")))
+    }
+
+    /// Summaries in the index, empty if there is none yet
+    fn read_index(&self) -> Vec<SavedEncounterSummary> {
+        self.get_index_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn write_index(
+        &self,
+        summaries: &[SavedEncounterSummary],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(summaries)?;
+        write_atomic(&self.get_index_path()?, json.as_bytes())?;
+        Ok(())
+    }
+
+    fn update_index(
+        &self,
+        update: impl FnOnce(&mut Vec<SavedEncounterSummary>),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut summaries = self.read_index();
+        update(&mut summaries);
+        self.write_index(&summaries)
+    }
+
+    /// Deletes the oldest encounters until at most `MAX_SAVED_ENCOUNTERS` are left
+    pub fn prune(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("Encounter history is not available")?;
+        // ids are the fight start timestamps, so they sort by age without reading the files
+        let mut ids = fs::read_dir(dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.parse::<u128>().ok()
+            })
+            .collect::<Vec<_>>();
+        if ids.len() <= MAX_SAVED_ENCOUNTERS {
+            return Ok(());
+        }
+        ids.sort_unstable();
+        for id in &ids[..ids.len() - MAX_SAVED_ENCOUNTERS] {
+            self.delete(&id.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+pub type EncounterHistoryMutex = Mutex<EncounterHistory>;
+
+// Number of encounters that can be brought back after a reset
+pub const MAX_RESET_SNAPSHOTS: usize = 5;
+
+/// Encounters cleared by a reset, newest first, kept in memory so a reset can be undone
+#[derive(Debug, Default)]
+pub struct ResetSnapshots {
+    snapshots: VecDeque<Encounter>,
+}
+
+impl ResetSnapshots {
+    pub fn push(&mut self, encounter: Encounter) {
+        if encounter.time_fight_start_ms == 0 {
+            return; // nothing to undo
+        }
+        if self.snapshots.len() == MAX_RESET_SNAPSHOTS {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_front(encounter);
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<Encounter> {
+        self.snapshots.remove(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Encounter> {
+        self.snapshots.iter()
+    }
+}
+
+pub type ResetSnapshotsMutex = Mutex<ResetSnapshots>;
+
+struct ArchiveTask {
+    app_handle: AppHandle,
+    encounter: Encounter,
+    end_reason: EncounterEndReason,
+    player_state: PlayerState,
+}
+
+static ARCHIVE_SENDER: OnceLock<Sender<ArchiveTask>> = OnceLock::new();
+
+/// Queues a finished encounter to be saved to the history, encounters without any combat are
+/// skipped. Building the windows and writing the file happen on a writer thread, so packet
+/// processing carries on right away.
+pub fn archive_encounter(
+    app_handle: &AppHandle,
+    encounter: Encounter,
+    end_reason: EncounterEndReason,
+    player_state: &PlayerState,
+) {
+    if encounter.time_fight_start_ms == 0 {
+        return;
+    }
+    let task = ArchiveTask {
+        app_handle: app_handle.clone(),
+        encounter,
+        end_reason,
+        player_state: player_state.clone(),
+    };
+    if get_archive_sender().send(task).is_err() {
+        warn!("Encounter history writer is not running, encounter dropped");
+    }
+}
+
+fn get_archive_sender() -> &'static Sender<ArchiveTask> {
+    ARCHIVE_SENDER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<ArchiveTask>();
+
+        std::thread::spawn(move || {
+            while let Ok(task) = rx.recv() {
+                save_encounter(&task);
+            }
+        });
+
+        tx
+    })
+}
+
+fn save_encounter(task: &ArchiveTask) {
+    let ArchiveTask {
+        app_handle,
+        encounter,
+        end_reason,
+        player_state,
+    } = task;
+    let end_reason = *end_reason;
+    let meter_settings = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    let saved_encounter = {
+        let player_cache = app_handle.state::<PlayerCacheMutex>();
+        let player_cache = player_cache.lock().unwrap();
+        get_saved_encounter(
+            encounter,
+            Some(end_reason),
+            &player_cache,
+            player_state,
+            &meter_settings,
+        )
+    };
+
+    let history = app_handle.state::<EncounterHistoryMutex>();
+    let history = history.lock().unwrap();
+    match history.save(&saved_encounter) {
+        Ok(()) => info!("Encounter {} saved ({end_reason:?})", saved_encounter.id),
+        Err(e) => warn!("Failed to save encounter {}: {e}", saved_encounter.id),
+    }
+    if let Err(e) = history.prune() {
+        warn!("Failed to remove old encounters: {e}");
+    }
+    drop(history);
+
+    let webhook_config = app_handle
+        .state::<WebhookConfigMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    if webhook_config.enabled {
+        let summary = get_encounter_summary(&saved_encounter, end_reason);
+        send_encounter_summary(&webhook_config, &summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::commands_models::{HeaderInfo, PlayersWindow, SavedEncounter};
+    use crate::live::encounter_history::EncounterHistory;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn get_saved_encounter(id: &str, time_fight_start_ms: f64) -> SavedEncounter {
+        SavedEncounter {
+            id: id.to_string(),
+            name: format!("Encounter {id}"),
+            end_reason: None,
+            time_fight_start_ms,
+            time_last_combat_packet_ms: time_fight_start_ms,
+            header_info: HeaderInfo::default(),
+            dps_players: PlayersWindow::default(),
+            dps_boss_only_players: PlayersWindow::default(),
+            heal_players: PlayersWindow::default(),
+            dps_skills: HashMap::new(),
+            dps_boss_only_skills: HashMap::new(),
+            heal_skills: HashMap::new(),
+            line_id: None,
+            level_map_id: None,
+            entities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_history_index() {
+        let dir = std::env::temp_dir().join(format!("bpsr-logs-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let history = EncounterHistory::new(Some(dir.clone()));
+
+        history.save(&get_saved_encounter("1", 1.0)).unwrap();
+        history.save(&get_saved_encounter("2", 2.0)).unwrap();
+        history.rename("1", "Renamed".to_string()).unwrap();
+        let summaries = history.list().unwrap();
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Encounter 2", "Renamed"]
+        );
+        assert!(!dir.join("1.tmp").exists());
+
+        // files changed behind the index's back are picked up again
+        fs::remove_file(dir.join("2.json")).unwrap();
+        fs::write(
+            dir.join("3.json"),
+            serde_json::to_string(&get_saved_encounter("3", 3.0)).unwrap(),
+        )
+        .unwrap();
+        let summaries = history.list().unwrap();
+        assert_eq!(
+            summaries.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(),
+            ["3", "1"]
+        );
+        assert_eq!(history.read_index().len(), 2);
+
+        history.delete("3").unwrap();
+        assert_eq!(history.read_index().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use crate::live::combat_log;
+use crate::live::commands_models::{EncounterQuery, QueryStatKind, QueryTargets};
+use crate::live::meter_settings::TargetFilter;
+use crate::live::opcodes_models::{Encounter, MONSTER_NAMES_BOSS};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Projects the logged events of `encounter` that match `query` into a new encounter, so the usual
+/// windows can be built from it. Elapsed time of the result is the queried time range.
+pub fn project_encounter(
+    encounter: &Encounter,
+    query: &EncounterQuery,
+    target_filters: &[TargetFilter],
+) -> Result<Encounter, String> {
+    let attacker_uids = query
+        .attacker_uid_strs
+        .iter()
+        .map(|uid_str| {
+            uid_str
+                .parse::<i64>()
+                .map_err(|e| format!("Invalid attacker uid {uid_str}: {e}"))
+        })
+        .collect::<Result<Vec<i64>, String>>()?;
+    let target_filter = match &query.targets {
+        QueryTargets::Filter(filter_id) => Some(
+            target_filters
+                .iter()
+                .find(|target_filter| target_filter.id == *filter_id)
+                .ok_or_else(|| format!("Could not find target filter with id {filter_id}"))?,
+        ),
+        _ => None,
+    };
+    if let (Some(start_ms), Some(end_ms)) = (query.start_ms, query.end_ms)
+        && start_ms > end_ms
+    {
+        return Err(format!(
+            "Query starts at {start_ms}ms after it ends at {end_ms}ms"
+        ));
+    }
+
+    let mut projection = encounter.without_stats();
+    if encounter.time_fight_start_ms == 0 {
+        return Ok(projection); // nothing happened yet
+    }
+    let range_start_ms =
+        encounter.time_fight_start_ms + query.start_ms.map_or(0, |ms| ms.max(0.0) as u128);
+    // A range starting after the fight ended is empty instead of ending before it starts
+    let range_end_ms = query
+        .end_ms
+        .map_or(encounter.time_last_combat_packet_ms, |ms| {
+            (encounter.time_fight_start_ms + ms.max(0.0) as u128)
+                .min(encounter.time_last_combat_packet_ms)
+        })
+        .max(range_start_ms);
+    projection.time_fight_start_ms = range_start_ms;
+    projection.time_last_combat_packet_ms = range_start_ms;
+
+    let is_heal_query = query.stat_kind == QueryStatKind::Heal;
+    let mut summon_uid_to_summoner_uid = HashMap::new();
+    combat_log::project(
+        &mut projection,
+        &encounter.combat_log,
+        &[],
+        |mut combat_event| {
+            if combat_event.is_heal != is_heal_query
+                || combat_event.timestamp_ms < range_start_ms
+                || combat_event.timestamp_ms > range_end_ms
+                || (!attacker_uids.is_empty()
+                    && !attacker_uids.contains(&combat_event.attacker_uid))
+            {
+                return None;
+            }
+
+            let target_entity = encounter.entity_uid_to_entity.get(&combat_event.target_uid);
+            let target_monster_id = target_entity.and_then(|e| e.monster_id);
+            let is_target_match = match &query.targets {
+                QueryTargets::All => true,
+                QueryTargets::Bosses => {
+                    target_monster_id.is_some_and(|id| MONSTER_NAMES_BOSS.contains_key(&id))
+                }
+                QueryTargets::Filter(_) => target_filter.is_some_and(|target_filter| {
+                    target_filter.matches(target_monster_id, target_entity.and_then(|e| e.max_hp))
+                }),
+                QueryTargets::MonsterIds(monster_ids) => {
+                    target_monster_id.is_some_and(|id| monster_ids.contains(&id))
+                }
+            };
+            if !is_target_match {
+                return None;
+            }
+            if !query.merge_summons && combat_event.is_summon() {
+                summon_uid_to_summoner_uid
+                    .insert(combat_event.summon_uid, combat_event.attacker_uid);
+                combat_event.attacker_uid = combat_event.summon_uid;
+            }
+            Some(combat_event)
+        },
+    );
+    for (summon_uid, summoner_uid) in summon_uid_to_summoner_uid {
+        let summon_entity = Arc::make_mut(
+            projection
+                .entity_uid_to_entity
+                .entry(summon_uid)
+                .or_default(),
+        );
+        summon_entity.summoner_uid = Some(summoner_uid);
+    }
+    // Time range ends where asked, not at the last matching event
+    if query.end_ms.is_some() {
+        projection.time_last_combat_packet_ms = range_end_ms;
+    }
+    Ok(projection)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::combat_log::{CombatEvent, record};
+    use crate::live::commands_models::{EncounterQuery, QueryStatKind, QueryTargets};
+    use crate::live::encounter_query::project_encounter;
+    use crate::live::opcodes_models::Encounter;
+
+    fn get_encounter() -> Encounter {
+        let mut encounter = Encounter {
+            time_fight_start_ms: 1000,
+            ..Default::default()
+        };
+        for (timestamp_ms, summon_uid) in [(1000, 0), (2000, 7), (3000, 0)] {
+            record(
+                &mut encounter,
+                &CombatEvent {
+                    timestamp_ms,
+                    attacker_uid: 1,
+                    summon_uid,
+                    target_uid: 2,
+                    skill_uid: 10,
+                    value: 100,
+                    ..Default::default()
+                },
+                &[],
+            );
+        }
+        encounter
+    }
+
+    fn get_query(
+        start_ms: Option<f64>,
+        end_ms: Option<f64>,
+        merge_summons: bool,
+    ) -> EncounterQuery {
+        EncounterQuery {
+            stat_kind: QueryStatKind::Dmg,
+            targets: QueryTargets::All,
+            attacker_uid_strs: Vec::new(),
+            start_ms,
+            end_ms,
+            merge_summons,
+            player_uid_str: None,
+        }
+    }
+
+    #[test]
+    fn test_query_range() {
+        let encounter = get_encounter();
+        assert!(
+            project_encounter(&encounter, &get_query(Some(500.0), Some(100.0), true), &[]).is_err()
+        );
+
+        // Starts after the fight ended
+        let projection = project_encounter(
+            &encounter,
+            &get_query(Some(5000.0), Some(6000.0), true),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(projection.time_fight_start_ms, 6000);
+        assert_eq!(projection.time_last_combat_packet_ms, 6000);
+        assert_eq!(projection.dmg_stats.value, 0);
+    }
+
+    #[test]
+    fn test_query_summons() {
+        let encounter = get_encounter();
+        let projection = project_encounter(&encounter, &get_query(None, None, true), &[]).unwrap();
+        assert_eq!(projection.entity_uid_to_entity[&1].dmg_stats.value, 300);
+        assert!(!projection.entity_uid_to_entity.contains_key(&7));
+
+        let projection = project_encounter(&encounter, &get_query(None, None, false), &[]).unwrap();
+        assert_eq!(projection.dmg_stats.value, 300);
+        assert_eq!(projection.entity_uid_to_entity[&1].dmg_stats.value, 200);
+        let summon_entity = &projection.entity_uid_to_entity[&7];
+        assert_eq!(summon_entity.dmg_stats.value, 100);
+        assert_eq!(summon_entity.summoner_uid, Some(1));
+    }
+}
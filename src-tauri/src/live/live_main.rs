@@ -1,199 +1,361 @@
 use crate::live::bptimer_state::{BPTimerEnabledMutex, is_bptimer_enabled};
-use crate::live::opcodes_models::EncounterMutex;
+use crate::live::combat_log::recompute_encounter;
+use crate::live::encounter_history::{EncounterEndReason, ResetSnapshotsMutex, archive_encounter};
+use crate::live::meter_settings::{MeterSettingsMutex, TargetFilter};
+use crate::live::opcodes_models::Encounter;
 use crate::live::opcodes_process::{
-    on_server_change, process_aoi_sync_delta, process_sync_container_data,
-    process_sync_near_entities, process_sync_to_me_delta_info,
+    get_encounter_end_reason, on_server_change, process_aoi_sync_delta,
+    process_sync_container_data, process_sync_near_entities, process_sync_to_me_delta_info,
 };
-use crate::live::player_state::{PlayerCacheMutex, PlayerStateMutex};
+use crate::live::player_state::{PlayerCacheMutex, PlayerState, PlayerStateMutex};
 use crate::packets;
+use crate::packets::pipeline_metrics::{PIPELINE_METRICS, increment};
 use crate::protocol::pb;
 use bytes::Bytes;
 use log::{info, warn};
 use prost::Message;
+use std::sync::Arc;
+use std::time::Duration;
 use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::Instant;
 
 fn decode_packet<T: Message + Default>(data: Vec<u8>, packet_name: &str) -> Option<T> {
     match T::decode(Bytes::from(data)) {
         Ok(v) => Some(v),
         Err(e) => {
             warn!("Error decoding {packet_name}.. ignoring: {e}");
+            increment(&PIPELINE_METRICS.decode_errors);
             None
         }
     }
 }
 
-pub async fn start(app_handle: AppHandle) {
+// Moves the current encounter to the history if this delta starts a new fight
+fn segment_encounter(
+    app_handle: &AppHandle,
+    encounter: &mut Encounter,
+    aoi_sync_delta: &pb::AoiSyncDelta,
+    player_state: &PlayerState,
+    timestamp_ms: u128,
+) {
+    let idle_timeout_secs = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .encounter_idle_timeout_secs;
+    let Some(end_reason) = get_encounter_end_reason(
+        encounter,
+        aoi_sync_delta,
+        timestamp_ms,
+        idle_timeout_secs as u128 * 1000,
+    ) else {
+        return;
+    };
+    let finished_encounter = encounter.start_next();
+    archive_encounter(app_handle, finished_encounter, end_reason, player_state);
+}
+
+// Saves the current encounter and starts a fresh one without any entities, since the uids belong to the old line
+fn on_line_change(app_handle: &AppHandle, encounter: &mut Encounter, player_state: &PlayerState) {
+    let finished_encounter = encounter.start_next();
+    archive_encounter(
+        app_handle,
+        finished_encounter,
+        EncounterEndReason::LineChange,
+        player_state,
+    );
+    encounter.entity_uid_to_entity.clear();
+}
+
+/// Changes to the encounter requested by commands, applied by the task that owns it
+pub enum EncounterControl {
+    Reset,
+    TogglePause {
+        timestamp_ms: u128,
+    },
+    Restore {
+        index: usize,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    SetTargetFilters(Arc<[TargetFilter]>),
+}
+
+pub type EncounterControlSender = mpsc::UnboundedSender<EncounterControl>;
+
+// Snapshots are published once the packet queue is drained, or at least this often under load
+pub async fn start(
+    app_handle: AppHandle,
+    encounter_publisher: watch::Sender<Arc<Encounter>>,
+    mut control_receiver: mpsc::UnboundedReceiver<EncounterControl>,
+) {
     let mut rx = packets::packet_capture::start_capture();
 
-    let bptimer_enabled_state = app_handle.state::<BPTimerEnabledMutex>();
+    // Only this task touches the encounter, everyone else reads the published snapshots.
+    // Snapshots share entities with the encounter, an entity is only copied the first time it
+    // changes after a publish. Packets are still batched into one snapshot per live update
+    // interval, so busy entities are copied once per interval instead of once per packet.
+    let mut encounter = Encounter::default();
+    let mut is_unpublished = false;
+    let mut next_publish = Instant::now();
 
     // 2. Use the channel to receive packets back and process them
-    while let Some((op, data)) = rx.recv().await {
-        {
-            let state = app_handle.state::<EncounterMutex>();
-            let encounter = state.lock().unwrap();
-            if encounter.is_encounter_paused {
-                continue;
+    loop {
+        tokio::select! {
+            Some(control) = control_receiver.recv() => {
+                on_encounter_control(&app_handle, &mut encounter, control);
+                // Commands read the snapshot right after, so they have to see their change
+                encounter_publisher.send_replace(Arc::new(encounter.clone()));
+                is_unpublished = false;
+            }
+            packet = rx.recv() => {
+                let Some((op, data, timestamp_ms)) = packet else {
+                    break;
+                };
+                increment(&PIPELINE_METRICS.messages_processed);
+                process_packet(&app_handle, &mut encounter, op, data, timestamp_ms);
+                is_unpublished = true;
             }
+            _ = tokio::time::sleep_until(next_publish), if is_unpublished => {}
+        }
+        if is_unpublished && Instant::now() >= next_publish {
+            encounter_publisher.send_replace(Arc::new(encounter.clone()));
+            is_unpublished = false;
+            let interval_ms = app_handle
+                .state::<MeterSettingsMutex>()
+                .lock()
+                .unwrap()
+                .live_update_interval_ms;
+            next_publish = Instant::now() + Duration::from_millis(u64::from(interval_ms));
+        }
+    }
+}
+
+fn on_encounter_control(
+    app_handle: &AppHandle,
+    encounter: &mut Encounter,
+    control: EncounterControl,
+) {
+    match control {
+        EncounterControl::Reset => {
+            let player_state = app_handle.state::<PlayerStateMutex>();
+            let player_state = player_state.lock().unwrap();
+            let reset_encounter = std::mem::take(encounter);
+            archive_encounter(
+                app_handle,
+                reset_encounter.clone(),
+                EncounterEndReason::Reset,
+                &player_state,
+            );
+            let reset_snapshots = app_handle.state::<ResetSnapshotsMutex>();
+            reset_snapshots.lock().unwrap().push(reset_encounter);
+            info!("encounter reset");
         }
-        match op {
-            packets::opcodes::Pkt::ServerChangeInfo => {
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                on_server_change(&mut encounter_state);
+        EncounterControl::TogglePause { timestamp_ms } => {
+            if encounter.is_encounter_paused {
+                encounter.resume(timestamp_ms);
+                info!("encounter resumed");
+            } else {
+                encounter.pause(timestamp_ms);
+                info!("encounter paused");
             }
-            packets::opcodes::Pkt::NotifySocialData => {
-                let Some(notify) = decode_packet::<pb::NotifySocialData>(data, "NotifySocialData")
-                else {
-                    continue;
-                };
+        }
+        EncounterControl::Restore { index, reply } => {
+            let reset_snapshots = app_handle.state::<ResetSnapshotsMutex>();
+            let mut reset_snapshots = reset_snapshots.lock().unwrap();
+            let result = match reset_snapshots.take(index) {
+                Some(mut restored_encounter) => {
+                    // Keep state that is newer than the snapshot
+                    restored_encounter.is_encounter_paused = encounter.is_encounter_paused;
+                    restored_encounter.time_pause_start_ms = encounter.time_pause_start_ms;
+                    restored_encounter.local_player = encounter.local_player.take();
+                    restored_encounter.uid_to_monster_info =
+                        std::mem::take(&mut encounter.uid_to_monster_info);
 
-                let scene_data = notify
-                    .v_request
-                    .as_ref()
-                    .and_then(|r| r.data.as_ref())
-                    .and_then(|s| s.scene_data.as_ref());
+                    let current_encounter = std::mem::replace(encounter, restored_encounter);
+                    reset_snapshots.push(current_encounter);
+                    info!("encounter restored from reset snapshot {index}");
+                    Ok(())
+                }
+                None => Err(format!("No reset snapshot at index {index}")),
+            };
+            let _ = reply.send(result); // the command may have given up waiting
+        }
+        EncounterControl::SetTargetFilters(target_filters) => {
+            recompute_encounter(encounter, &target_filters);
+        }
+    }
+}
 
-                if let Some(scene) = scene_data {
-                    let player_state_mutex = app_handle.state::<PlayerStateMutex>();
-                    let mut player_state = player_state_mutex.lock().unwrap();
+fn process_packet(
+    app_handle: &AppHandle,
+    encounter: &mut Encounter,
+    op: packets::opcodes::Pkt,
+    data: Vec<u8>,
+    timestamp_ms: u128,
+) {
+    let bptimer_enabled_state = app_handle.state::<BPTimerEnabledMutex>();
+    match op {
+        packets::opcodes::Pkt::ServerChangeInfo => {
+            let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+            let player_state = player_state_mutex.lock().unwrap();
+            archive_encounter(
+                app_handle,
+                encounter.start_next(),
+                EncounterEndReason::ServerChange,
+                &player_state,
+            );
+            on_server_change(encounter);
+        }
+        packets::opcodes::Pkt::NotifySocialData => {
+            let Some(notify) = decode_packet::<pb::NotifySocialData>(data, "NotifySocialData")
+            else {
+                return;
+            };
 
-                    let old_line = player_state.get_line_id_opt();
-                    if scene.line_id != 0 {
-                        player_state.set_line_id(scene.line_id);
-                    }
-                    if scene.level_map_id != 0 {
-                        player_state.set_level_map_id(scene.level_map_id);
-                    }
+            let scene_data = notify
+                .v_request
+                .as_ref()
+                .and_then(|r| r.data.as_ref())
+                .and_then(|s| s.scene_data.as_ref());
 
-                    if old_line != Some(scene.line_id) && scene.line_id != 0 {
-                        info!(
-                            "[SocialNtf] scene changed: line_id={} level_map_id={}",
-                            scene.line_id, scene.level_map_id
-                        );
-                        let encounter_state = app_handle.state::<EncounterMutex>();
-                        let mut encounter_state = encounter_state.lock().unwrap();
-                        encounter_state.entity_uid_to_entity.clear();
-                    }
+            if let Some(scene) = scene_data {
+                let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+                let mut player_state = player_state_mutex.lock().unwrap();
+
+                let old_line = player_state.get_line_id_opt();
+                if scene.line_id != 0 {
+                    player_state.set_line_id(scene.line_id);
+                }
+                if scene.level_map_id != 0 {
+                    player_state.set_level_map_id(scene.level_map_id);
                 }
-            }
-            packets::opcodes::Pkt::NotifyEnterWorld => {
-                let Some(notify) = decode_packet::<pb::NotifyEnterWorld>(data, "NotifyEnterWorld")
-                else {
-                    continue;
-                };
 
-                let scene_ip = notify
-                    .v_request
-                    .as_ref()
-                    .map(|r| r.scene_ip.clone())
-                    .unwrap_or_default();
-
-                if !scene_ip.is_empty() {
-                    let player_state_mutex = app_handle.state::<PlayerStateMutex>();
-                    let mut player_state = player_state_mutex.lock().unwrap();
-                    if player_state.set_scene_ip(scene_ip.clone()) {
-                        info!("[NotifyEnterWorld] scene_ip={scene_ip}");
-                    }
+                if old_line != Some(scene.line_id) && scene.line_id != 0 {
+                    info!(
+                        "[SocialNtf] scene changed: line_id={} level_map_id={}",
+                        scene.line_id, scene.level_map_id
+                    );
+                    on_line_change(app_handle, encounter, &player_state);
                 }
             }
-            packets::opcodes::Pkt::SyncNearEntities => {
-                let Some(sync_near_entities) =
-                    decode_packet::<pb::SyncNearEntities>(data, "SyncNearEntities")
-                else {
-                    continue;
-                };
+        }
+        packets::opcodes::Pkt::NotifyEnterWorld => {
+            let Some(notify) = decode_packet::<pb::NotifyEnterWorld>(data, "NotifyEnterWorld")
+            else {
+                return;
+            };
+
+            let scene_ip = notify
+                .v_request
+                .as_ref()
+                .map(|r| r.scene_ip.clone())
+                .unwrap_or_default();
+
+            if !scene_ip.is_empty() {
                 let player_state_mutex = app_handle.state::<PlayerStateMutex>();
-                let player_state = player_state_mutex.lock().unwrap();
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
-                if process_sync_near_entities(
-                    &mut encounter_state,
-                    sync_near_entities,
-                    &player_state,
-                    is_bptimer_enabled(&bptimer_enabled_state),
-                    Some(&player_cache_mutex),
-                )
-                .is_none()
-                {
-                    warn!("Error processing SyncNearEntities.. ignoring.");
+                let mut player_state = player_state_mutex.lock().unwrap();
+                if player_state.set_scene_ip(scene_ip.clone()) {
+                    info!("[NotifyEnterWorld] scene_ip={scene_ip}");
                 }
             }
-            packets::opcodes::Pkt::SyncContainerData => {
-                let Some(sync_container_data) =
-                    decode_packet::<pb::SyncContainerData>(data, "SyncContainerData")
-                else {
-                    continue;
-                };
+        }
+        packets::opcodes::Pkt::SyncNearEntities => {
+            let Some(sync_near_entities) =
+                decode_packet::<pb::SyncNearEntities>(data, "SyncNearEntities")
+            else {
+                return;
+            };
+            let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+            let player_state = player_state_mutex.lock().unwrap().clone(); // copied so readers aren't blocked while processing
+            let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
+            if process_sync_near_entities(
+                encounter,
+                sync_near_entities,
+                &player_state,
+                is_bptimer_enabled(&bptimer_enabled_state),
+                Some(&player_cache_mutex),
+            )
+            .is_none()
+            {
+                warn!("Error processing SyncNearEntities.. ignoring.");
+            }
+        }
+        packets::opcodes::Pkt::SyncContainerData => {
+            let Some(sync_container_data) =
+                decode_packet::<pb::SyncContainerData>(data, "SyncContainerData")
+            else {
+                return;
+            };
 
-                // Store persistent player identity data
-                let mut should_clear_entities = false;
-                if let Some(v_data) = &sync_container_data.v_data {
-                    let player_state_mutex = app_handle.state::<PlayerStateMutex>();
-                    let mut player_state = player_state_mutex.lock().unwrap();
-
-                    // Extract and store account_id and uid
-                    if let Some(char_base) = &v_data.char_base {
-                        if !char_base.account_id.is_empty() && v_data.char_id != 0 {
-                            player_state
-                                .set_account_info(char_base.account_id.clone(), v_data.char_id);
-                        }
+            // Store persistent player identity data
+            let mut should_clear_entities = false;
+            if let Some(v_data) = &sync_container_data.v_data {
+                let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+                let mut player_state = player_state_mutex.lock().unwrap();
+
+                // Extract and store account_id and uid
+                if let Some(char_base) = &v_data.char_base {
+                    if !char_base.account_id.is_empty() && v_data.char_id != 0 {
+                        player_state.set_account_info(char_base.account_id.clone(), v_data.char_id);
                     }
+                }
 
-                    // Extract and store line_id
-                    if let Some(scene_data) = &v_data.scene_data {
-                        if scene_data.line_id != 0 {
-                            let old_line_id = player_state.get_line_id_opt();
-                            player_state.set_line_id(scene_data.line_id);
-                            if old_line_id != Some(scene_data.line_id) {
-                                should_clear_entities = true;
-                            }
+                // Extract and store line_id
+                if let Some(scene_data) = &v_data.scene_data {
+                    if scene_data.line_id != 0 {
+                        let old_line_id = player_state.get_line_id_opt();
+                        player_state.set_line_id(scene_data.line_id);
+                        if old_line_id != Some(scene_data.line_id) {
+                            should_clear_entities = true;
                         }
                     }
                 }
+            }
 
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                if should_clear_entities {
-                    encounter_state.entity_uid_to_entity.clear();
-                }
-                let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
-                encounter_state.local_player = Some(sync_container_data.clone());
-                if process_sync_container_data(
-                    &mut encounter_state,
-                    sync_container_data,
-                    Some(&player_cache_mutex),
-                )
-                .is_none()
-                {
-                    warn!("Error processing SyncContainerData.. ignoring.");
-                }
+            let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+            let player_state = player_state_mutex.lock().unwrap();
+            if should_clear_entities {
+                on_line_change(app_handle, encounter, &player_state);
             }
-            // packets::opcodes::Pkt::SyncContainerDirtyData => {
-            //     // info!("Received {op:?}");
-            //     // trace!("Received {op:?} and data {data:?}");
-            //     let sync_container_dirty_data =
-            //         match blueprotobuf::SyncContainerDirtyData::decode(Bytes::from(data)) {
-            //             Ok(v) => v,
-            //             Err(e) => {
-            //                 warn!("Error decoding SyncContainerDirtyData.. ignoring: {e}");
-            //                 continue;
-            //             }
-            //         };
-            //     let encounter_state = app_handle.state::<EncounterMutex>();
-            //     let mut encounter_state = encounter_state.lock().unwrap();
-            //     if process_sync_container_dirty_data(&mut encounter_state, sync_container_dirty_data).is_none() {
-            //         warn!("Error processing SyncContainerDirtyData.. ignoring.");
-            //     }
-            // }
-            packets::opcodes::Pkt::SyncToMeDeltaInfo => {
-                let Some(sync_to_me_delta_info) =
-                    decode_packet::<pb::SyncToMeDeltaInfo>(data, "SyncToMeDeltaInfo")
-                else {
-                    continue;
-                };
+            let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
+            encounter.local_player = Some(sync_container_data.clone());
+            if process_sync_container_data(
+                encounter,
+                sync_container_data,
+                Some(&player_cache_mutex),
+            )
+            .is_none()
+            {
+                warn!("Error processing SyncContainerData.. ignoring.");
+            }
+        }
+        // packets::opcodes::Pkt::SyncContainerDirtyData => {
+        //     // info!("Received {op:?}");
+        //     // trace!("Received {op:?} and data {data:?}");
+        //     let sync_container_dirty_data =
+        //         match blueprotobuf::SyncContainerDirtyData::decode(Bytes::from(data)) {
+        //             Ok(v) => v,
+        //             Err(e) => {
+        //                 warn!("Error decoding SyncContainerDirtyData.. ignoring: {e}");
+        //                 return;
+        //             }
+        //         };
+        //     let encounter_state = app_handle.state::<EncounterMutex>();
+        //     let mut encounter_state = encounter_state.lock().unwrap();
+        //     if process_sync_container_dirty_data(encounter, sync_container_dirty_data).is_none() {
+        //         warn!("Error processing SyncContainerDirtyData.. ignoring.");
+        //     }
+        // }
+        packets::opcodes::Pkt::SyncToMeDeltaInfo => {
+            let Some(sync_to_me_delta_info) =
+                decode_packet::<pb::SyncToMeDeltaInfo>(data, "SyncToMeDeltaInfo")
+            else {
+                return;
+            };
 
+            // Copied so readers aren't blocked while processing
+            let player_state = {
                 let player_state_mutex = app_handle.state::<PlayerStateMutex>();
                 let mut player_state = player_state_mutex.lock().unwrap();
 
@@ -209,45 +371,75 @@ pub async fn start(app_handle: AppHandle) {
                         }
                     }
                 }
-
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
-                if process_sync_to_me_delta_info(
-                    &mut encounter_state,
-                    sync_to_me_delta_info,
+                player_state.clone()
+            };
+            if let Some(base_delta) = sync_to_me_delta_info
+                .delta_info
+                .as_ref()
+                .and_then(|delta_info| delta_info.base_delta.as_ref())
+            {
+                segment_encounter(
+                    app_handle,
+                    encounter,
+                    base_delta,
+                    &player_state,
+                    timestamp_ms,
+                );
+            }
+            let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
+            let meter_settings = app_handle
+                .state::<MeterSettingsMutex>()
+                .lock()
+                .unwrap()
+                .clone();
+            if process_sync_to_me_delta_info(
+                encounter,
+                sync_to_me_delta_info,
+                &player_state,
+                is_bptimer_enabled(&bptimer_enabled_state),
+                Some(&player_cache_mutex),
+                &meter_settings,
+                timestamp_ms,
+            )
+            .is_none()
+            {
+                warn!("Error processing SyncToMeDeltaInfo.. ignoring.");
+            }
+        }
+        packets::opcodes::Pkt::SyncNearDeltaInfo => {
+            let Some(sync_near_delta_info) =
+                decode_packet::<pb::SyncNearDeltaInfo>(data, "SyncNearDeltaInfo")
+            else {
+                return;
+            };
+            let player_state_mutex = app_handle.state::<PlayerStateMutex>();
+            let player_state = player_state_mutex.lock().unwrap().clone(); // copied so readers aren't blocked while processing
+            let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
+            let meter_settings = app_handle
+                .state::<MeterSettingsMutex>()
+                .lock()
+                .unwrap()
+                .clone();
+            for aoi_sync_delta in sync_near_delta_info.delta_infos {
+                segment_encounter(
+                    app_handle,
+                    encounter,
+                    &aoi_sync_delta,
+                    &player_state,
+                    timestamp_ms,
+                );
+                if process_aoi_sync_delta(
+                    encounter,
+                    aoi_sync_delta,
                     &player_state,
                     is_bptimer_enabled(&bptimer_enabled_state),
                     Some(&player_cache_mutex),
+                    &meter_settings,
+                    timestamp_ms,
                 )
                 .is_none()
                 {
-                    warn!("Error processing SyncToMeDeltaInfo.. ignoring.");
-                }
-            }
-            packets::opcodes::Pkt::SyncNearDeltaInfo => {
-                let Some(sync_near_delta_info) =
-                    decode_packet::<pb::SyncNearDeltaInfo>(data, "SyncNearDeltaInfo")
-                else {
-                    continue;
-                };
-                let player_state_mutex = app_handle.state::<PlayerStateMutex>();
-                let player_state = player_state_mutex.lock().unwrap();
-                let encounter_state = app_handle.state::<EncounterMutex>();
-                let mut encounter_state = encounter_state.lock().unwrap();
-                let player_cache_mutex = app_handle.state::<PlayerCacheMutex>();
-                for aoi_sync_delta in sync_near_delta_info.delta_infos {
-                    if process_aoi_sync_delta(
-                        &mut encounter_state,
-                        aoi_sync_delta,
-                        &player_state,
-                        is_bptimer_enabled(&bptimer_enabled_state),
-                        Some(&player_cache_mutex),
-                    )
-                    .is_none()
-                    {
-                        warn!("Error processing SyncNearDeltaInfo.. ignoring.");
-                    }
+                    warn!("Error processing SyncNearDeltaInfo.. ignoring.");
                 }
             }
         }
@@ -0,0 +1,235 @@
+use crate::live::commands::{PlayerWindowCache, StatType, get_header, get_player_window};
+use crate::live::commands_models::{HeaderInfo, PlayerRows, PlayersWindow};
+use crate::live::meter_settings::MeterSettingsMutex;
+use crate::live::opcodes_models::{Encounter, EncounterSnapshot};
+use crate::live::player_state::{PlayerCacheMutex, PlayerStateMutex};
+use log::warn;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+use tokio::sync::broadcast;
+
+#[derive(serde::Serialize, serde::Deserialize, specta::Type, tauri_specta::Event, Debug, Clone)]
+pub struct HeaderInfoUpdated(pub HeaderInfo);
+
+#[derive(serde::Serialize, serde::Deserialize, specta::Type, tauri_specta::Event, Debug, Clone)]
+pub struct DpsPlayersUpdated(pub PlayersWindow);
+
+#[derive(serde::Serialize, serde::Deserialize, specta::Type, tauri_specta::Event, Debug, Clone)]
+pub struct DpsBossOnlyPlayersUpdated(pub PlayersWindow);
+
+#[derive(serde::Serialize, serde::Deserialize, specta::Type, tauri_specta::Event, Debug, Clone)]
+pub struct HealPlayersUpdated(pub PlayersWindow);
+
+/// Live window update for subscribers outside the webview (e.g. the local API). Players windows
+/// are sent whole to new subscribers (see `get_live_updates`), after that only as row diffs.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum LiveUpdate {
+    HeaderInfo(HeaderInfo),
+    DpsPlayers(PlayersWindow),
+    DpsBossOnlyPlayers(PlayersWindow),
+    HealPlayers(PlayersWindow),
+    DpsPlayersDiff(PlayersWindowDiff),
+    DpsBossOnlyPlayersDiff(PlayersWindowDiff),
+    HealPlayersDiff(PlayersWindowDiff),
+}
+
+/// Rows of a players window that changed since the last update, the other rows stay as they were.
+/// `uids` is the new row order, only sent when rows were added, removed or moved.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayersWindowDiff {
+    pub changed_rows: PlayerRows,
+    pub uids: Option<Vec<f64>>,
+    pub local_player_uid: f64,
+    pub top_value: f64,
+}
+
+pub type LiveUpdateSender = broadcast::Sender<LiveUpdate>;
+
+// Slow subscribers past this many pending updates lag and have to resync
+pub const LIVE_UPDATE_CAPACITY: usize = 64;
+
+struct LiveWindows {
+    header_info: HeaderInfo,
+    dps_players: PlayersWindow,
+    dps_boss_only_players: PlayersWindow,
+    heal_players: PlayersWindow,
+}
+
+// Last emitted snapshots, nothing is emitted while they stay the same
+#[derive(Default)]
+struct LiveSnapshots {
+    // Windows are only updated once the encounter or the rolling window changed
+    encounter: Option<Arc<Encounter>>,
+    rolling_window_secs: u32,
+    header_info: Option<HeaderInfo>,
+    dps_players: PlayerWindowCache,
+    dps_boss_only_players: PlayerWindowCache,
+    heal_players: PlayerWindowCache,
+}
+
+/// Emits the live meter windows every `MeterSettings::live_update_interval_ms`, but only
+/// the ones that changed since the last tick, so the frontend doesn't have to poll
+pub async fn start(app_handle: AppHandle) {
+    let mut last_snapshots = LiveSnapshots::default();
+    loop {
+        let interval_ms = app_handle
+            .state::<MeterSettingsMutex>()
+            .lock()
+            .unwrap()
+            .live_update_interval_ms;
+        tokio::time::sleep(Duration::from_millis(u64::from(interval_ms))).await;
+        emit_changed(&app_handle, &mut last_snapshots);
+    }
+}
+
+fn get_live_windows(app_handle: &AppHandle, encounter: &Encounter) -> LiveWindows {
+    let meter_settings = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    let player_state = app_handle.state::<PlayerStateMutex>();
+    let player_state = player_state.lock().unwrap();
+    let player_cache = app_handle.state::<PlayerCacheMutex>();
+    let player_cache = player_cache.lock().unwrap();
+    let get_window = |stat_type| {
+        get_player_window(
+            encounter,
+            stat_type,
+            &player_cache,
+            &player_state,
+            &meter_settings,
+        )
+    };
+    LiveWindows {
+        header_info: get_header(encounter, meter_settings.rolling_window_secs),
+        dps_players: get_window(StatType::Dmg),
+        dps_boss_only_players: get_window(StatType::DmgBossOnly),
+        heal_players: get_window(StatType::Heal),
+    }
+}
+
+/// Every live window as it is now, for new subscribers that haven't seen any update yet
+pub fn get_live_updates(app_handle: &AppHandle) -> Vec<LiveUpdate> {
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    let live_windows = get_live_windows(app_handle, &encounter);
+    vec![
+        LiveUpdate::HeaderInfo(live_windows.header_info),
+        LiveUpdate::DpsPlayers(live_windows.dps_players),
+        LiveUpdate::DpsBossOnlyPlayers(live_windows.dps_boss_only_players),
+        LiveUpdate::HealPlayers(live_windows.heal_players),
+    ]
+}
+
+fn emit_changed(app_handle: &AppHandle, last_snapshots: &mut LiveSnapshots) {
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    let meter_settings = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    let is_unchanged = last_snapshots
+        .encounter
+        .as_ref()
+        .is_some_and(|last_encounter| Arc::ptr_eq(last_encounter, &encounter))
+        && last_snapshots.rolling_window_secs == meter_settings.rolling_window_secs;
+    if is_unchanged {
+        return;
+    }
+    {
+        let player_state = app_handle.state::<PlayerStateMutex>();
+        let player_state = player_state.lock().unwrap();
+        let player_cache = app_handle.state::<PlayerCacheMutex>();
+        let player_cache = player_cache.lock().unwrap();
+        let windows = [
+            (&mut last_snapshots.dps_players, StatType::Dmg),
+            (
+                &mut last_snapshots.dps_boss_only_players,
+                StatType::DmgBossOnly,
+            ),
+            (&mut last_snapshots.heal_players, StatType::Heal),
+        ];
+        for (player_window_cache, stat_type) in windows {
+            player_window_cache.update(
+                &encounter,
+                stat_type,
+                &player_cache,
+                &player_state,
+                &meter_settings,
+            );
+        }
+    }
+    let header_info = get_header(&encounter, meter_settings.rolling_window_secs);
+    last_snapshots.encounter = Some(encounter);
+    last_snapshots.rolling_window_secs = meter_settings.rolling_window_secs;
+    let live_update_sender = app_handle.state::<LiveUpdateSender>();
+    // Skips the copy when nobody outside the webview is listening
+    let publish = |live_update: &dyn Fn() -> LiveUpdate| {
+        if live_update_sender.receiver_count() > 0 {
+            let _ = live_update_sender.send(live_update());
+        }
+    };
+
+    if let Some(header_info) = take_if_changed(&mut last_snapshots.header_info, header_info) {
+        publish(&|| LiveUpdate::HeaderInfo(header_info.clone()));
+        emit(app_handle, HeaderInfoUpdated(header_info));
+    }
+    let dps_players = &last_snapshots.dps_players;
+    if dps_players.is_changed() {
+        publish(&|| LiveUpdate::DpsPlayersDiff(get_players_window_diff(dps_players)));
+        emit(app_handle, DpsPlayersUpdated(dps_players.window.clone()));
+    }
+    let dps_boss_only_players = &last_snapshots.dps_boss_only_players;
+    if dps_boss_only_players.is_changed() {
+        publish(&|| {
+            LiveUpdate::DpsBossOnlyPlayersDiff(get_players_window_diff(dps_boss_only_players))
+        });
+        emit(
+            app_handle,
+            DpsBossOnlyPlayersUpdated(dps_boss_only_players.window.clone()),
+        );
+    }
+    let heal_players = &last_snapshots.heal_players;
+    if heal_players.is_changed() {
+        publish(&|| LiveUpdate::HealPlayersDiff(get_players_window_diff(heal_players)));
+        emit(app_handle, HealPlayersUpdated(heal_players.window.clone()));
+    }
+}
+
+fn get_players_window_diff(player_window_cache: &PlayerWindowCache) -> PlayersWindowDiff {
+    let player_rows = &player_window_cache.window.player_rows;
+    PlayersWindowDiff {
+        changed_rows: player_window_cache
+            .changed_row_indices
+            .iter()
+            .map(|&row_index| player_rows[row_index].clone())
+            .collect(),
+        uids: player_window_cache.is_reordered.then(|| {
+            player_rows
+                .iter()
+                .map(|player_row| player_row.uid)
+                .collect()
+        }),
+        local_player_uid: player_window_cache.window.local_player_uid,
+        top_value: player_window_cache.window.top_value,
+    }
+}
+
+// Stores `snapshot` as the last one and returns it if it differs from the previous one
+fn take_if_changed<T: PartialEq + Clone>(last: &mut Option<T>, snapshot: T) -> Option<T> {
+    if last.as_ref() == Some(&snapshot) {
+        return None;
+    }
+    *last = Some(snapshot.clone());
+    Some(snapshot)
+}
+
+fn emit<E: Event + serde::Serialize + Clone>(app_handle: &AppHandle, event: E) {
+    if let Err(e) = event.emit(app_handle) {
+        warn!("Failed to emit {}: {e}", E::NAME);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::live::commands::{StatType, get_header, get_player_window, get_skill_window};
+use crate::live::commands_models::{HeaderInfo, PlayersWindow, SkillsWindow};
+use crate::live::live_updates::{LiveUpdate, LiveUpdateSender, get_live_updates};
+use crate::live::meter_settings::MeterSettingsMutex;
+use crate::live::metrics::get_prometheus_metrics;
+use crate::live::opcodes_models::EncounterSnapshot;
+use crate::live::player_state::{PlayerCacheMutex, PlayerStateMutex};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use log::{info, warn};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tokio::sync::{broadcast, watch};
+
+pub const DEFAULT_LOCAL_API_PORT: u16 = 7878;
+
+// Self-contained meter page for OBS browser sources, see the file for its query parameters
+const OVERLAY_HTML: &str = include_str!("local_api/overlay.html");
+
+/// Settings of the optional server on 127.0.0.1 that exposes the live windows to overlays and bots
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Required as `Authorization: Bearer <token>` or `?token=<token>` when not empty
+    pub token: String,
+}
+
+impl Default for LocalApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_LOCAL_API_PORT,
+            token: String::new(),
+        }
+    }
+}
+
+// Dropping the shutdown sender stops the server and every open WebSocket
+pub struct RunningServer {
+    shutdown: watch::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+pub type LocalApiServerMutex = Mutex<Option<RunningServer>>;
+
+#[derive(Clone)]
+struct ApiState {
+    app_handle: AppHandle,
+    token: Option<Arc<str>>,
+    // Origins of the server's own pages, the only ones browsers may call the API from
+    allowed_origins: Arc<[String]>,
+    shutdown: watch::Receiver<()>,
+}
+
+// Browsers can't set headers on WebSockets, so the token is also accepted as a query parameter
+#[derive(serde::Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// Live window selected in the url, e.g. `/api/players/dps-boss-only`
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum WindowMode {
+    Dps,
+    DpsBossOnly,
+    Heal,
+}
+
+impl From<WindowMode> for StatType {
+    fn from(mode: WindowMode) -> Self {
+        match mode {
+            WindowMode::Dps => StatType::Dmg,
+            WindowMode::DpsBossOnly => StatType::DmgBossOnly,
+            WindowMode::Heal => StatType::Heal,
+        }
+    }
+}
+
+/// Stops the running server, if any, and starts a new one when `config` is enabled.
+/// Waits for the old server to release its port, so the same port can be reused right away.
+pub async fn restart(app_handle: &AppHandle, config: LocalApiConfig) {
+    let running_server = app_handle
+        .state::<LocalApiServerMutex>()
+        .lock()
+        .unwrap()
+        .take();
+    if let Some(RunningServer { shutdown, task }) = running_server {
+        drop(shutdown);
+        if let Err(e) = task.await {
+            warn!("Local API server task failed: {e}");
+        }
+        info!("Local API stopped");
+    }
+    if !config.enabled {
+        return;
+    }
+
+    let (shutdown, shutdown_receiver) = watch::channel(());
+    let api_state = ApiState {
+        app_handle: app_handle.clone(),
+        token: Some(config.token.trim())
+            .filter(|token| !token.is_empty())
+            .map(Arc::from),
+        allowed_origins: Arc::from([
+            format!("http://127.0.0.1:{}", config.port),
+            format!("http://localhost:{}", config.port),
+        ]),
+        shutdown: shutdown_receiver,
+    };
+    let task = tauri::async_runtime::spawn(serve(config.port, api_state));
+    *app_handle.state::<LocalApiServerMutex>().lock().unwrap() =
+        Some(RunningServer { shutdown, task });
+}
+
+async fn serve(port: u16, api_state: ApiState) {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to start local API on {addr}: {e}");
+            return;
+        }
+    };
+    info!("Local API listening on http://{addr}");
+
+    let mut shutdown = api_state.shutdown.clone();
+    let router = Router::new()
+        .route("/api/header", get(get_header_handler))
+        .route("/api/players/{mode}", get(get_players_handler))
+        .route("/api/skills/{mode}/{player_uid}", get(get_skills_handler))
+        .route("/api/ws", get(ws_handler))
+        .route("/overlay", get(Html(OVERLAY_HTML)))
+        .route("/metrics", get(get_metrics_handler))
+        .layer(middleware::from_fn_with_state(
+            api_state.clone(),
+            require_token,
+        ))
+        .layer(middleware::from_fn_with_state(
+            api_state.clone(),
+            require_own_origin,
+        ))
+        .with_state(api_state);
+    if let Err(e) = axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.changed().await;
+        })
+        .await
+    {
+        warn!("Local API server error: {e}");
+    }
+}
+
+/// Rejects requests made by pages of other sites. Browsers always send `Origin` on WebSockets
+/// and cross origin requests, so without this any open web page could read the meter when no
+/// token is set. Overlays and bots that aren't browsers don't send it and pass.
+async fn require_own_origin(
+    State(api_state): State<ApiState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(origin) = request.headers().get(header::ORIGIN) {
+        let is_own_origin = origin.to_str().is_ok_and(|origin| {
+            api_state
+                .allowed_origins
+                .iter()
+                .any(|allowed_origin| allowed_origin == origin)
+        });
+        if !is_own_origin {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn require_token(
+    State(api_state): State<ApiState>,
+    Query(token_query): Query<TokenQuery>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = &api_state.token else {
+        return next.run(request).await;
+    };
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let request_token = bearer.or(token_query.token.as_deref());
+    if request_token != Some(&**token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+fn get_header_info(app_handle: &AppHandle) -> HeaderInfo {
+    let rolling_window_secs = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .rolling_window_secs;
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    get_header(&encounter, rolling_window_secs)
+}
+
+fn get_players(app_handle: &AppHandle, stat_type: StatType) -> PlayersWindow {
+    let meter_settings = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    let player_state = app_handle.state::<PlayerStateMutex>();
+    let player_state = player_state.lock().unwrap();
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    let player_cache = app_handle.state::<PlayerCacheMutex>();
+    let player_cache = player_cache.lock().unwrap();
+    get_player_window(
+        &*encounter,
+        stat_type,
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    )
+}
+
+async fn get_header_handler(State(api_state): State<ApiState>) -> Json<HeaderInfo> {
+    Json(get_header_info(&api_state.app_handle))
+}
+
+async fn get_players_handler(
+    State(api_state): State<ApiState>,
+    Path(mode): Path<WindowMode>,
+) -> Json<PlayersWindow> {
+    Json(get_players(&api_state.app_handle, mode.into()))
+}
+
+async fn get_skills_handler(
+    State(api_state): State<ApiState>,
+    Path((mode, player_uid)): Path<(WindowMode, i64)>,
+) -> Result<Json<SkillsWindow>, (StatusCode, String)> {
+    let app_handle = &api_state.app_handle;
+    let meter_settings = app_handle
+        .state::<MeterSettingsMutex>()
+        .lock()
+        .unwrap()
+        .clone();
+    let player_state = app_handle.state::<PlayerStateMutex>();
+    let player_state = player_state.lock().unwrap();
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    let player_cache = app_handle.state::<PlayerCacheMutex>();
+    let player_cache = player_cache.lock().unwrap();
+    get_skill_window(
+        encounter,
+        player_uid,
+        mode.into(),
+        &player_cache,
+        &player_state,
+        &meter_settings,
+    )
+    .map(Json)
+    .map_err(|e| (StatusCode::NOT_FOUND, e))
+}
+
+async fn get_metrics_handler(State(api_state): State<ApiState>) -> impl IntoResponse {
+    let app_handle = &api_state.app_handle;
+    let header_info = get_header_info(app_handle);
+    let dps_players = get_players(app_handle, StatType::Dmg);
+    let encounter = app_handle.state::<EncounterSnapshot>().borrow().clone();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        get_prometheus_metrics(&encounter, &header_info, &dps_players),
+    )
+}
+
+async fn ws_handler(State(api_state): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| stream_live_updates(socket, api_state))
+}
+
+/// Sends every live window whole once, then only what changed as `LiveUpdate` JSON: the header
+/// whole, players windows as row diffs. Missing an update starts over with whole windows.
+async fn stream_live_updates(mut socket: WebSocket, mut api_state: ApiState) {
+    let mut live_updates = api_state.app_handle.state::<LiveUpdateSender>().subscribe();
+    if send_all(&mut socket, &api_state.app_handle).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            live_update = live_updates.recv() => {
+                let sent = match live_update {
+                    Ok(live_update) => send_live_update(&mut socket, &live_update).await,
+                    // Missed some changes, start over from the current windows
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        send_all(&mut socket, &api_state.app_handle).await
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if sent.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => {} // nothing to do with client messages
+            },
+            _ = api_state.shutdown.changed() => {
+                let _ = socket.send(Message::Close(None)).await;
+                return;
+            }
+        }
+    }
+}
+
+async fn send_all(socket: &mut WebSocket, app_handle: &AppHandle) -> Result<(), axum::Error> {
+    for live_update in get_live_updates(app_handle) {
+        send_live_update(socket, &live_update).await?;
+    }
+    Ok(())
+}
+
+async fn send_live_update(
+    socket: &mut WebSocket,
+    live_update: &LiveUpdate,
+) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(live_update).expect("LiveUpdate is always serializable");
+    socket.send(Message::Text(json.into())).await
+}
@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_ROLLING_WINDOW_SECS: u32 = 10;
+pub const MAX_ROLLING_WINDOW_SECS: u32 = 60; // keep in sync with the settings slider
+pub const DEFAULT_ENCOUNTER_IDLE_TIMEOUT_SECS: u32 = 60;
+pub const DEFAULT_LIVE_UPDATE_INTERVAL_MS: u32 = 200;
+pub const MIN_LIVE_UPDATE_INTERVAL_MS: u32 = 50;
+pub const MAX_LIVE_UPDATE_INTERVAL_MS: u32 = 1000; // keep in sync with the settings slider
+
+/// What has to happen before the fight clock starts, damage before that is ignored
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq,
+)]
+pub enum FightStartTrigger {
+    /// First damage by anyone
+    #[default]
+    Any,
+    /// First damage dealt by the local player
+    LocalPlayer,
+    /// First damage dealt to a boss
+    Boss,
+    /// First damage dealt to this monster id
+    Monster(u32),
+}
+
+/// User defined set of targets to get separate damage stats for, e.g. when the real target
+/// isn't in the boss list. Only monsters can match.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetFilter {
+    pub id: u32,
+    pub name: String,
+    pub include_monster_ids: Vec<u32>, // empty = all monsters
+    pub exclude_monster_ids: Vec<u32>,
+    pub min_max_hp: f64, // excludes adds below this max hp, 0 = no limit
+}
+
+impl TargetFilter {
+    pub fn matches(&self, monster_id: Option<u32>, max_hp: Option<u64>) -> bool {
+        let Some(monster_id) = monster_id else {
+            return false;
+        };
+        if !self.include_monster_ids.is_empty() && !self.include_monster_ids.contains(&monster_id) {
+            return false;
+        }
+        if self.exclude_monster_ids.contains(&monster_id) {
+            return false;
+        }
+        self.min_max_hp <= 0.0 || max_hp.is_some_and(|max_hp| max_hp as f64 >= self.min_max_hp)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MeterSettings {
+    pub rolling_window_secs: u32,
+    pub encounter_idle_timeout_secs: u32, // 0 = never split on idle
+    pub fight_start_trigger: FightStartTrigger,
+    pub target_filters: Arc<[TargetFilter]>, // shared, since the settings are cloned for every packet
+    pub live_update_interval_ms: u32,
+}
+
+impl Default for MeterSettings {
+    fn default() -> Self {
+        Self {
+            rolling_window_secs: DEFAULT_ROLLING_WINDOW_SECS,
+            encounter_idle_timeout_secs: DEFAULT_ENCOUNTER_IDLE_TIMEOUT_SECS,
+            fight_start_trigger: FightStartTrigger::default(),
+            target_filters: Arc::from([]),
+            live_update_interval_ms: DEFAULT_LIVE_UPDATE_INTERVAL_MS,
+        }
+    }
+}
+
+impl MeterSettings {
+    pub fn set_rolling_window_secs(&mut self, secs: u32) {
+        self.rolling_window_secs = secs.clamp(1, MAX_ROLLING_WINDOW_SECS);
+    }
+
+    pub fn set_live_update_interval_ms(&mut self, ms: u32) {
+        self.live_update_interval_ms =
+            ms.clamp(MIN_LIVE_UPDATE_INTERVAL_MS, MAX_LIVE_UPDATE_INTERVAL_MS);
+    }
+}
+
+pub type MeterSettingsMutex = Mutex<MeterSettings>;
@@ -0,0 +1,163 @@
+use crate::live::commands_models::{HeaderInfo, PlayersWindow};
+use crate::live::opcodes_models::{Encounter, MONSTER_NAMES_BOSS};
+use crate::packets::pipeline_metrics::{PIPELINE_METRICS, get};
+use std::fmt::Write;
+
+/// Encounter and capture pipeline metrics in the Prometheus text exposition format
+pub fn get_prometheus_metrics(
+    encounter: &Encounter,
+    header_info: &HeaderInfo,
+    dps_players: &PlayersWindow,
+) -> String {
+    let mut metrics = String::new();
+
+    write_help(
+        &mut metrics,
+        "bpsr_raid_dps",
+        "gauge",
+        "Damage per second of everyone in the current encounter",
+    );
+    let _ = writeln!(metrics, "bpsr_raid_dps {}", header_info.total_dps);
+
+    write_help(
+        &mut metrics,
+        "bpsr_encounter_duration_seconds",
+        "gauge",
+        "Time since the current encounter started",
+    );
+    let _ = writeln!(
+        metrics,
+        "bpsr_encounter_duration_seconds {}",
+        header_info.elapsed_ms / 1000.0
+    );
+
+    write_help(
+        &mut metrics,
+        "bpsr_player_dps",
+        "gauge",
+        "Damage per second of each player in the current encounter",
+    );
+    for player_row in &dps_players.player_rows {
+        let _ = writeln!(
+            metrics,
+            "bpsr_player_dps{{uid=\"{}\",name=\"{}\",class=\"{}\"}} {}",
+            player_row.uid,
+            escape_label(&player_row.name),
+            escape_label(&player_row.class_name),
+            player_row.value_per_sec
+        );
+    }
+
+    write_help(
+        &mut metrics,
+        "bpsr_boss_hp_percent",
+        "gauge",
+        "Remaining HP of each boss seen in the current encounter",
+    );
+    for (uid, entity) in &encounter.entity_uid_to_entity {
+        let Some(boss_name) = entity
+            .monster_id
+            .and_then(|monster_id| MONSTER_NAMES_BOSS.get(&monster_id))
+        else {
+            continue;
+        };
+        let (Some(curr_hp), Some(max_hp)) = (entity.curr_hp, entity.max_hp) else {
+            continue;
+        };
+        if max_hp == 0 {
+            continue;
+        }
+        let _ = writeln!(
+            metrics,
+            "bpsr_boss_hp_percent{{uid=\"{uid}\",monster_id=\"{}\",name=\"{}\"}} {}",
+            entity.monster_id.unwrap_or_default(),
+            escape_label(boss_name),
+            curr_hp as f64 / max_hp as f64 * 100.0
+        );
+    }
+
+    let counters = [
+        (
+            "bpsr_frames_captured_total",
+            "Frames read from the capture driver",
+            &PIPELINE_METRICS.frames_captured,
+        ),
+        (
+            "bpsr_packets_reassembled_total",
+            "Game packets cut from the reassembled TCP stream",
+            &PIPELINE_METRICS.packets_reassembled,
+        ),
+        (
+            "bpsr_messages_processed_total",
+            "Decoded messages received by the encounter processing",
+            &PIPELINE_METRICS.messages_processed,
+        ),
+        (
+            "bpsr_decode_errors_total",
+            "Malformed fragments, failed decompression or protobuf decoding",
+            &PIPELINE_METRICS.decode_errors,
+        ),
+        (
+            "bpsr_reassembly_drops_total",
+            "Times stream data was thrown away because of an invalid packet size",
+            &PIPELINE_METRICS.reassembly_drops,
+        ),
+        (
+            "bpsr_channel_drops_total",
+            "Messages lost because the processing channel was full or closed",
+            &PIPELINE_METRICS.channel_drops,
+        ),
+    ];
+    for (name, help, counter) in counters {
+        write_help(&mut metrics, name, "counter", help);
+        let _ = writeln!(metrics, "{name} {}", get(counter));
+    }
+
+    metrics
+}
+
+fn write_help(metrics: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(metrics, "# HELP {name} {help}");
+    let _ = writeln!(metrics, "# TYPE {name} {metric_type}");
+}
+
+// Label values are quoted, so backslashes, quotes and newlines have to be escaped
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::commands_models::{HeaderInfo, PlayerRow, PlayersWindow};
+    use crate::live::metrics::get_prometheus_metrics;
+    use crate::live::opcodes_models::Encounter;
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let header_info = HeaderInfo {
+            total_dps: 1500.0,
+            elapsed_ms: 30_000.0,
+            ..Default::default()
+        };
+        let dps_players = PlayersWindow {
+            player_rows: vec![PlayerRow {
+                uid: 42.0,
+                name: "Quote\"Name".to_string(),
+                class_name: "Stormblade".into(),
+                value_per_sec: 1000.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let metrics = get_prometheus_metrics(&Encounter::default(), &header_info, &dps_players);
+        assert!(metrics.contains("\nbpsr_raid_dps 1500\n"));
+        assert!(metrics.contains("\nbpsr_encounter_duration_seconds 30\n"));
+        assert!(metrics.contains(
+            "\nbpsr_player_dps{uid=\"42\",name=\"Quote\\\"Name\",class=\"Stormblade\"} 1000\n"
+        ));
+        assert!(metrics.contains("# TYPE bpsr_decode_errors_total counter\n"));
+    }
+}
@@ -1,22 +1,44 @@
+use crate::live::combat_log::CombatLog;
+use crate::live::meter_settings::MAX_ROLLING_WINDOW_SECS;
 use crate::live::opcodes_models::class::{Class, ClassSpec};
 use crate::protocol::pb;
 use crate::protocol::pb::{EEntityType, SyncContainerData};
-use std::collections::HashMap;
-use std::sync::{LazyLock, Mutex};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::watch;
 
-pub type EncounterMutex = Mutex<Encounter>;
+/// Latest copy of the encounter, published by the packet processing task that owns it
+/// (see `live_main::start`), so reading it never waits on packet processing
+pub type EncounterSnapshot = watch::Receiver<Arc<Encounter>>;
 
 #[derive(Debug, Default, Clone)]
 pub struct Encounter {
     pub is_encounter_paused: bool,
+    pub time_pause_start_ms: u128,
     pub time_last_combat_packet_ms: u128,
+    pub time_last_dmg_ms: u128, // heals don't count, so they can't keep an idle encounter going
     pub time_fight_start_ms: u128,
-    pub entity_uid_to_entity: HashMap<i64, Entity>,
+    pub is_boss_defeated: bool,
+    // Shared with the published snapshots, `Arc::make_mut` copies an entity when it changes
+    pub entity_uid_to_entity: HashMap<i64, Arc<Entity>>,
     pub uid_to_monster_info: HashMap<i64, (u32, u64)>,
     pub dmg_stats: CombatStats,
     pub dmg_stats_boss_only: CombatStats,
     pub heal_stats: CombatStats,
+    pub dmg_stats_filtered: HashMap<u32, CombatStats>, // keyed by TargetFilter::id
+    pub dmg_rolling: RollingWindow,
+    // Entity uids ordered by their total, so player windows don't need sorting
+    pub dmg_ranking: Ranking<i64>,
+    pub dmg_ranking_boss_only: Ranking<i64>,
+    pub heal_ranking: Ranking<i64>,
+    pub dmg_ranking_filtered: HashMap<u32, Ranking<i64>>, // keyed by TargetFilter::id
+    pub combat_log: CombatLog,
     pub local_player: Option<SyncContainerData>,
+    // Scene the fight started in
+    pub line_id: Option<u32>,
+    pub level_map_id: Option<u32>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -32,12 +54,45 @@ pub struct Entity {
     pub heal_stats: CombatStats,
     pub skill_uid_to_heal_stats: HashMap<i32, CombatStats>,
 
+    // Skill uids ordered by their total, so skill windows don't need sorting
+    pub dps_skill_ranking: Ranking<i32>,
+    pub dps_skill_ranking_boss_only: Ranking<i32>,
+    pub heal_skill_ranking: Ranking<i32>,
+
+    // Timelines (relative to Encounter::time_fight_start_ms)
+    pub dmg_timeline: Timeline,
+    pub dmg_timeline_boss_only: Timeline,
+    pub heal_timeline: Timeline,
+
+    // Recent values for the sliding window "current" DPS
+    pub dmg_rolling: RollingWindow,
+    pub dmg_rolling_boss_only: RollingWindow,
+    pub heal_rolling: RollingWindow,
+
+    // First/last hit and active combat time
+    pub dmg_activity: Activity,
+    pub dmg_activity_boss_only: Activity,
+    pub heal_activity: Activity,
+
+    // Damage to targets matching the user defined target filters, keyed by TargetFilter::id
+    pub dmg_filtered: HashMap<u32, FilterStats>,
+
+    // Times this entity died while the fight clock was running
+    pub deaths: u32,
+    // Damage this entity took, heals not subtracted
+    pub dmg_taken: i64,
+    // Attacked or got hit at least once, only these entities are saved with the encounter
+    pub is_in_combat: bool,
+
     // Players
     pub name: Option<String>, // also available for monsters in packets
     pub class: Option<Class>,
     pub class_spec: Option<ClassSpec>,
     pub ability_score: Option<i32>,
 
+    // Summons, only set in query projections that give summon damage its own row
+    pub summoner_uid: Option<i64>,
+
     // Monsters
     pub monster_id: Option<u32>,
     pub curr_hp: Option<u64>, // also available for players in packets
@@ -45,6 +100,135 @@ pub struct Entity {
     pub monster_pos: pb::Vector3,
 }
 
+impl Encounter {
+    pub fn pause(&mut self, timestamp_ms: u128) {
+        if !self.is_encounter_paused {
+            self.is_encounter_paused = true;
+            self.time_pause_start_ms = timestamp_ms;
+        }
+    }
+
+    /// Shifts the fight forward by the time spent paused so it doesn't count towards elapsed time
+    pub fn resume(&mut self, timestamp_ms: u128) {
+        if !self.is_encounter_paused {
+            return;
+        }
+        self.is_encounter_paused = false;
+        if self.time_fight_start_ms != 0 {
+            let paused_ms = timestamp_ms.saturating_sub(self.time_pause_start_ms);
+            self.time_fight_start_ms += paused_ms;
+            self.time_last_combat_packet_ms += paused_ms;
+            self.time_last_dmg_ms += paused_ms;
+            self.combat_log.shift(paused_ms as u64);
+        }
+        self.time_pause_start_ms = 0;
+    }
+
+    /// Takes the current encounter out and leaves a fresh one in its place.
+    pub fn start_next(&mut self) -> Encounter {
+        let next = self.without_stats();
+        std::mem::replace(self, next)
+    }
+
+    /// A fresh encounter with the same entities and pause state but no fight yet.
+    /// Known entities are carried over without their stats, since names, classes and
+    /// monster ids are not resent by the server at the start of every fight.
+    pub fn without_stats(&self) -> Encounter {
+        Encounter {
+            is_encounter_paused: self.is_encounter_paused,
+            time_pause_start_ms: self.time_pause_start_ms,
+            entity_uid_to_entity: self
+                .entity_uid_to_entity
+                .iter()
+                .map(|(&uid, entity)| (uid, Arc::new(entity.without_stats())))
+                .collect(),
+            uid_to_monster_info: self.uid_to_monster_info.clone(),
+            local_player: self.local_player.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Entity {
+    pub fn without_stats(&self) -> Entity {
+        Entity {
+            entity_type: self.entity_type,
+            name: self.name.clone(),
+            class: self.class,
+            class_spec: self.class_spec,
+            ability_score: self.ability_score,
+            monster_id: self.monster_id,
+            curr_hp: self.curr_hp,
+            max_hp: self.max_hp,
+            monster_pos: self.monster_pos,
+            ..Default::default()
+        }
+    }
+}
+
+/// Everything tracked for the damage to one user defined target filter
+#[derive(Debug, Default, Clone)]
+pub struct FilterStats {
+    pub stats: CombatStats,
+    pub skill_uid_to_stats: HashMap<i32, CombatStats>,
+    pub skill_ranking: Ranking<i32>,
+    pub timeline: Timeline,
+    pub rolling: RollingWindow,
+    pub activity: Activity,
+}
+
+// Used for filters that haven't seen any damage yet
+pub static EMPTY_FILTER_STATS: LazyLock<FilterStats> = LazyLock::new(FilterStats::default);
+
+// Ranking of a target filter that no hit has matched yet
+pub static EMPTY_RANKING: LazyLock<Ranking<i64>> = LazyLock::new(Ranking::default);
+
+/// Keys ordered by descending total, kept in order as values are added. A hit only changes
+/// one total by a little, so the key usually moves a few places instead of resorting everything.
+#[derive(Debug, Clone)]
+pub struct Ranking<K> {
+    entries: Vec<(K, i64)>,
+    positions: HashMap<K, usize>,
+}
+
+impl<K> Default for Ranking<K> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            positions: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> Ranking<K> {
+    pub fn add(&mut self, key: K, value: i64) {
+        let mut pos = *self.positions.entry(key).or_insert_with(|| {
+            self.entries.push((key, 0));
+            self.entries.len() - 1
+        });
+        self.entries[pos].1 += value;
+        while pos > 0 && self.entries[pos - 1].1 < self.entries[pos].1 {
+            self.swap(pos - 1, pos);
+            pos -= 1;
+        }
+        while pos + 1 < self.entries.len() && self.entries[pos + 1].1 > self.entries[pos].1 {
+            self.swap(pos, pos + 1);
+            pos += 1;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        self.positions.insert(self.entries[a].0, a);
+        self.positions.insert(self.entries[b].0, b);
+    }
+
+    /// Keys from the highest to the lowest total
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.entries.iter().map(|&(key, _)| key)
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct CombatStats {
     pub value: i64,
@@ -53,6 +237,200 @@ pub struct CombatStats {
     pub crit_hits: i64,
     pub lucky_value: i64,
     pub lucky_hits: i64,
+    // Each hit goes into exactly one of these, so the multipliers can use the same hits
+    pub normal_hit_dist: HitDistribution, // neither crit nor lucky
+    pub crit_hit_dist: HitDistribution,   // crit but not lucky
+    pub lucky_hit_dist: HitDistribution,  // lucky but not crit
+    pub crit_lucky_hit_dist: HitDistribution,
+}
+
+// Relative accuracy of the hit size sketch, ~1% per bucket
+const HIT_SKETCH_GAMMA: f64 = 1.02;
+
+/// Min/max/mean of hit sizes plus a log-bucketed sketch for approximate percentiles.
+/// Bucket `i` holds values in (gamma^(i-1), gamma^i], so estimates are within ~1% of the real hit.
+#[derive(Debug, Default, Clone)]
+pub struct HitDistribution {
+    pub hits: i64,
+    pub value: i64,
+    pub min: i64,
+    pub max: i64,
+    buckets: BTreeMap<i32, i64>,
+}
+
+impl HitDistribution {
+    pub fn add(&mut self, value: i64) {
+        if self.hits == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.hits += 1;
+        self.value += value;
+        *self.buckets.entry(Self::bucket_index(value)).or_default() += 1;
+    }
+
+    fn bucket_index(value: i64) -> i32 {
+        if value <= 1 {
+            return 0;
+        }
+        ((value as f64).ln() / HIT_SKETCH_GAMMA.ln()).ceil() as i32
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.hits == 0 {
+            return 0.0;
+        }
+        self.value as f64 / self.hits as f64
+    }
+
+    /// Approximate value at quantile `q` (0.0..=1.0)
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.hits == 0 {
+            return 0.0;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.hits - 1) as f64).round() as i64;
+        let mut seen = 0;
+        for (&index, &count) in &self.buckets {
+            seen += count;
+            if seen > rank {
+                let estimate = 2.0 * HIT_SKETCH_GAMMA.powi(index) / (HIT_SKETCH_GAMMA + 1.0);
+                return estimate.clamp(self.min as f64, self.max as f64);
+            }
+        }
+        self.max as f64
+    }
+}
+
+pub const TIMELINE_BUCKET_MS: u128 = 1000;
+pub const MAX_TIMELINE_BUCKETS: usize = 600;
+
+/// Value dealt per time bucket, starting at 1s resolution. Once a fight outgrows
+/// `MAX_TIMELINE_BUCKETS`, neighbouring buckets are merged and the resolution halves.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    pub bucket_ms: u128,
+    pub buckets: Vec<i64>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            bucket_ms: TIMELINE_BUCKET_MS,
+            buckets: Vec::new(),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn add(&mut self, offset_ms: u128, value: i64) {
+        let mut idx = (offset_ms / self.bucket_ms) as usize;
+        while idx >= MAX_TIMELINE_BUCKETS {
+            self.compact();
+            idx = (offset_ms / self.bucket_ms) as usize;
+        }
+        if idx >= self.buckets.len() {
+            self.buckets.resize(idx + 1, 0);
+        }
+        self.buckets[idx] += value;
+    }
+
+    fn compact(&mut self) {
+        self.buckets = self
+            .buckets
+            .chunks(2)
+            .map(|pair| pair.iter().sum())
+            .collect();
+        self.bucket_ms *= 2;
+    }
+
+    /// Re-buckets into `bucket_ms` wide buckets, which must be a multiple of the current width.
+    pub fn resampled(&self, bucket_ms: u128, len: usize) -> Vec<i64> {
+        let factor = (bucket_ms / self.bucket_ms).max(1) as usize;
+        let mut resampled: Vec<i64> = self
+            .buckets
+            .chunks(factor)
+            .map(|chunk| chunk.iter().sum())
+            .collect();
+        resampled.resize(len, 0);
+        resampled
+    }
+}
+
+/// Value dealt per wall-clock second, keeping only the last `MAX_ROLLING_WINDOW_SECS` seconds.
+#[derive(Debug, Default, Clone)]
+pub struct RollingWindow {
+    buckets: VecDeque<(u128, i64)>, // (timestamp secs, value)
+}
+
+impl RollingWindow {
+    pub fn add(&mut self, timestamp_ms: u128, value: i64) {
+        let sec = timestamp_ms / 1000;
+        match self.buckets.back_mut() {
+            // late packets are counted in the latest second to keep the buckets ordered
+            Some((last_sec, last_value)) if *last_sec >= sec => *last_value += value,
+            _ => self.buckets.push_back((sec, value)),
+        }
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(first_sec, _)| first_sec + u128::from(MAX_ROLLING_WINDOW_SECS) <= sec)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Sum of the values in the `window_secs` seconds up to and including `now_ms`.
+    pub fn sum(&self, now_ms: u128, window_secs: u32) -> i64 {
+        let now_sec = now_ms / 1000;
+        self.buckets
+            .iter()
+            .rev()
+            .take_while(|(sec, _)| sec + u128::from(window_secs) > now_sec)
+            .map(|(_, value)| value)
+            .sum()
+    }
+}
+
+/// Gaps between hits longer than this are treated as idle time.
+pub const ACTIVITY_IDLE_GAP_MS: u128 = 5_000;
+// Shortest interval, so a lone hit still counts as one timeline bucket of activity
+pub const ACTIVITY_MIN_INTERVAL_MS: u128 = TIMELINE_BUCKET_MS;
+
+/// Tracks first hit, last hit and total active time, splitting activity into intervals
+/// whenever the gap between two hits exceeds `ACTIVITY_IDLE_GAP_MS`.
+#[derive(Debug, Default, Clone)]
+pub struct Activity {
+    pub first_hit_ms: u128,
+    pub last_hit_ms: u128,
+    closed_active_ms: u128,
+    interval_start_ms: u128,
+}
+
+impl Activity {
+    pub fn add(&mut self, timestamp_ms: u128) {
+        if self.first_hit_ms == 0 {
+            self.first_hit_ms = timestamp_ms;
+            self.interval_start_ms = timestamp_ms;
+        } else if timestamp_ms.saturating_sub(self.last_hit_ms) > ACTIVITY_IDLE_GAP_MS {
+            self.closed_active_ms += self.get_interval_ms();
+            self.interval_start_ms = timestamp_ms;
+        }
+        self.last_hit_ms = self.last_hit_ms.max(timestamp_ms);
+    }
+
+    pub fn active_ms(&self) -> u128 {
+        if self.first_hit_ms == 0 {
+            return 0;
+        }
+        self.closed_active_ms + self.get_interval_ms()
+    }
+
+    fn get_interval_ms(&self) -> u128 {
+        (self.last_hit_ms - self.interval_start_ms).max(ACTIVITY_MIN_INTERVAL_MS)
+    }
 }
 
 static SKILL_NAMES: LazyLock<HashMap<i32, String>> = LazyLock::new(|| {
@@ -61,11 +439,58 @@ static SKILL_NAMES: LazyLock<HashMap<i32, String>> = LazyLock::new(|| {
 });
 
 impl CombatStats {
-    pub fn get_skill_name(skill_uid: i32) -> String {
-        SKILL_NAMES
-            .get(&skill_uid)
-            .cloned()
-            .unwrap_or_else(|| format!("UNKNOWN SKILL ({skill_uid})"))
+    pub fn add_hit(&mut self, value: i64, is_crit: bool, is_lucky: bool) {
+        if is_crit {
+            self.crit_hits += 1;
+            self.crit_value += value;
+        }
+        if is_lucky {
+            self.lucky_hits += 1;
+            self.lucky_value += value;
+        }
+        let hit_dist = match (is_crit, is_lucky) {
+            (false, false) => &mut self.normal_hit_dist,
+            (true, false) => &mut self.crit_hit_dist,
+            (false, true) => &mut self.lucky_hit_dist,
+            (true, true) => &mut self.crit_lucky_hit_dist,
+        };
+        hit_dist.add(value);
+        self.hits += 1;
+        self.value += value;
+    }
+
+    /// Known names are borrowed from the static skill table instead of copied
+    pub fn get_skill_name(skill_uid: i32) -> Cow<'static, str> {
+        match SKILL_NAMES.get(&skill_uid) {
+            Some(skill_name) => Cow::Borrowed(skill_name),
+            None => Cow::Owned(format!("UNKNOWN SKILL ({skill_uid})")),
+        }
+    }
+
+    pub fn get_crit_only_hits(&self) -> i64 {
+        self.crit_hit_dist.hits
+    }
+
+    pub fn get_lucky_only_hits(&self) -> i64 {
+        self.lucky_hit_dist.hits
+    }
+
+    /// Average crit (non-lucky) hit divided by the average normal hit, if both were observed
+    pub fn get_crit_multiplier(&self) -> Option<f64> {
+        self.get_multiplier(&self.crit_hit_dist)
+    }
+
+    /// Average lucky (non-crit) hit divided by the average normal hit, if both were observed
+    pub fn get_lucky_multiplier(&self) -> Option<f64> {
+        self.get_multiplier(&self.lucky_hit_dist)
+    }
+
+    fn get_multiplier(&self, hit_dist: &HitDistribution) -> Option<f64> {
+        let avg_normal_hit = self.normal_hit_dist.mean();
+        if hit_dist.hits <= 0 || avg_normal_hit <= 0.0 {
+            return None;
+        }
+        Some(hit_dist.mean() / avg_normal_hit)
     }
 }
 
@@ -108,8 +533,8 @@ pub mod class {
         }
     }
 
-    pub fn get_class_name(class: Class) -> String {
-        String::from(match class {
+    pub fn get_class_name(class: Class) -> &'static str {
+        match class {
             Class::Stormblade => "Stormblade",
             Class::FrostMage => "Frost Mage",
             Class::WindKnight => "Wind Knight",
@@ -120,7 +545,7 @@ pub mod class {
             Class::BeatPerformer => "Beat Performer",
             Class::Unknown => "Unknown Class",
             Class::Unimplemented => "Unimplemented Class",
-        })
+        }
     }
 
     #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -190,8 +615,8 @@ pub mod class {
     }
 
     // TODO: is there a way to just do this automatically based on the name of the enum?
-    pub fn get_class_spec(class_spec: ClassSpec) -> String {
-        String::from(match class_spec {
+    pub fn get_class_spec(class_spec: ClassSpec) -> &'static str {
+        match class_spec {
             ClassSpec::Iaido => "Iaido",
             ClassSpec::Moonstrike => "Moonstrike",
             ClassSpec::Icicle => "Icicle",
@@ -209,6 +634,91 @@ pub mod class {
             ClassSpec::Dissonance => "Dissonance",
             ClassSpec::Concerto => "Concerto",
             ClassSpec::Unknown => "Unknown Spec",
-        })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::opcodes_models::{
+        ACTIVITY_IDLE_GAP_MS, ACTIVITY_MIN_INTERVAL_MS, Activity, CombatStats, HitDistribution,
+        Ranking,
+    };
+
+    #[test]
+    fn test_activity_single_hit() {
+        let mut activity = Activity::default();
+        assert_eq!(activity.active_ms(), 0);
+        activity.add(10_000);
+        assert_eq!(activity.active_ms(), ACTIVITY_MIN_INTERVAL_MS);
+    }
+
+    #[test]
+    fn test_activity_spaced_hits() {
+        let mut activity = Activity::default();
+        for i in 0..3 {
+            activity.add(10_000 + i * (ACTIVITY_IDLE_GAP_MS + 1));
+        }
+        assert_eq!(activity.active_ms(), 3 * ACTIVITY_MIN_INTERVAL_MS);
+
+        // Hits within the gap extend the interval past the minimum
+        activity.add(10_000 + 2 * (ACTIVITY_IDLE_GAP_MS + 1) + 3_000);
+        assert_eq!(activity.active_ms(), 2 * ACTIVITY_MIN_INTERVAL_MS + 3_000);
+    }
+
+    #[test]
+    fn test_hit_distribution_quantiles() {
+        let mut dist = HitDistribution::default();
+        for value in 1..=1000 {
+            dist.add(value * 100);
+        }
+        assert_eq!(dist.min, 100);
+        assert_eq!(dist.max, 100_000);
+        assert!((dist.mean() - 50_050.0).abs() < f64::EPSILON);
+        for (q, expected) in [(0.5, 50_000.0), (0.9, 90_000.0), (0.99, 99_000.0)] {
+            let estimate = dist.quantile(q);
+            assert!(
+                (estimate - expected).abs() / expected < 0.02,
+                "p{q} estimate {estimate} too far from {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_crit_lucky_hits() {
+        let mut stats = CombatStats::default();
+        stats.add_hit(100, false, false);
+        stats.add_hit(200, true, false);
+        stats.add_hit(150, false, true);
+        stats.add_hit(400, true, true);
+        assert_eq!(stats.hits, 4);
+        assert_eq!((stats.crit_hits, stats.crit_value), (2, 600));
+        assert_eq!((stats.lucky_hits, stats.lucky_value), (2, 550));
+        for hit_dist in [
+            &stats.normal_hit_dist,
+            &stats.crit_hit_dist,
+            &stats.lucky_hit_dist,
+            &stats.crit_lucky_hit_dist,
+        ] {
+            assert_eq!(hit_dist.hits, 1);
+        }
+        // The crit+lucky hit counts for neither multiplier nor their distributions
+        assert_eq!(stats.crit_hit_dist.max, 200);
+        assert_eq!(stats.get_crit_multiplier(), Some(2.0));
+        assert_eq!(stats.lucky_hit_dist.max, 150);
+        assert_eq!(stats.get_lucky_multiplier(), Some(1.5));
+    }
+
+    #[test]
+    fn test_ranking_order() {
+        let mut ranking = Ranking::default();
+        ranking.add(1, 100);
+        ranking.add(2, 50);
+        ranking.add(3, 75);
+        assert_eq!(ranking.keys().collect::<Vec<_>>(), vec![1, 3, 2]);
+        ranking.add(2, 60);
+        assert_eq!(ranking.keys().collect::<Vec<_>>(), vec![2, 1, 3]);
+        ranking.add(3, 40);
+        assert_eq!(ranking.keys().collect::<Vec<_>>(), vec![3, 2, 1]);
     }
 }
@@ -1,8 +1,11 @@
 use crate::live::bptimer::BPTimerClient;
+use crate::live::combat_log::{self, CombatEvent};
+use crate::live::encounter_history::EncounterEndReason;
+use crate::live::meter_settings::{FightStartTrigger, MeterSettings};
 use crate::live::opcodes_models::class::{
     Class, ClassSpec, get_class_from_spec, get_class_spec_from_skill_id,
 };
-use crate::live::opcodes_models::{CombatStats, Encounter, Entity, MONSTER_NAMES_BOSS};
+use crate::live::opcodes_models::{Encounter, Entity, MONSTER_NAMES_BOSS};
 use crate::live::player_state::{PlayerCacheMutex, PlayerState};
 use crate::packets::utils::BinaryReader;
 use crate::protocol::constants::{attr_type, damage, entity};
@@ -12,8 +15,7 @@ use log::{debug, info, warn};
 use prost::Message;
 use std::collections::HashMap;
 use std::io::Cursor;
-use std::sync::LazyLock;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, LazyLock};
 
 // Needed for Github Actions compile-time env vars
 const COMPILE_TIME_ENDPOINT: Option<&str> = option_env!("BP_TIMER_ENDPOINT");
@@ -53,6 +55,42 @@ pub fn on_server_change(encounter: &mut Encounter) {
     encounter.uid_to_monster_info = preserved_monster_info;
 }
 
+/// Decides whether the current encounter should be closed before `aoi_sync_delta` is processed.
+/// Only damage starts a new encounter, so the finished one stays visible until then.
+pub fn get_encounter_end_reason(
+    encounter: &Encounter,
+    aoi_sync_delta: &pb::AoiSyncDelta,
+    timestamp_ms: u128,
+    idle_timeout_ms: u128,
+) -> Option<EncounterEndReason> {
+    if encounter.time_fight_start_ms == 0 || encounter.is_encounter_paused {
+        return None;
+    }
+    // `damages` carries heals too, which shouldn't end or extend an idle encounter
+    let has_damage = aoi_sync_delta
+        .skill_effects
+        .as_ref()
+        .is_some_and(|skill_effect| {
+            skill_effect
+                .damages
+                .iter()
+                .any(|sync_damage_info| sync_damage_info.r#type != pb::EDamageType::Heal as i32)
+        });
+    if !has_damage {
+        return None;
+    }
+
+    if encounter.is_boss_defeated {
+        return Some(EncounterEndReason::BossDefeated);
+    }
+    if idle_timeout_ms > 0
+        && timestamp_ms.saturating_sub(encounter.time_last_dmg_ms) > idle_timeout_ms
+    {
+        return Some(EncounterEndReason::IdleTimeout);
+    }
+    None
+}
+
 pub fn process_sync_near_entities(
     encounter: &mut Encounter,
     sync_near_entities: pb::SyncNearEntities,
@@ -68,10 +106,12 @@ pub fn process_sync_near_entities(
         let target_uid = entity::get_player_uid(target_uuid);
         let target_entity_type = pb::EEntityType::from(target_uuid);
 
-        let target_entity = encounter
-            .entity_uid_to_entity
-            .entry(target_uid)
-            .or_default();
+        let target_entity = Arc::make_mut(
+            encounter
+                .entity_uid_to_entity
+                .entry(target_uid)
+                .or_default(),
+        );
         target_entity.entity_type = target_entity_type;
 
         if let Some(attrs) = &pkt_entity.attrs {
@@ -112,10 +152,12 @@ pub fn process_sync_container_data(
         return None;
     }
 
-    let target_entity = encounter
-        .entity_uid_to_entity
-        .entry(player_uid)
-        .or_default();
+    let target_entity = Arc::make_mut(
+        encounter
+            .entity_uid_to_entity
+            .entry(player_uid)
+            .or_default(),
+    );
     target_entity.entity_type = pb::EEntityType::EntChar;
 
     if let Some(char_base) = &v_data.char_base {
@@ -159,6 +201,8 @@ pub fn process_sync_to_me_delta_info(
     player_state: &PlayerState,
     is_bptimer_enabled: bool,
     player_cache: Option<&PlayerCacheMutex>,
+    meter_settings: &MeterSettings,
+    timestamp_ms: u128,
 ) -> Option<()> {
     let Some(delta_info) = &sync_to_me_delta_info.delta_info else {
         return None;
@@ -172,15 +216,20 @@ pub fn process_sync_to_me_delta_info(
         player_state,
         is_bptimer_enabled,
         player_cache,
+        meter_settings,
+        timestamp_ms,
     )
 }
 
+/// `timestamp_ms` is the capture time of the packet, so stats don't depend on processing delay
 pub fn process_aoi_sync_delta(
     encounter: &mut Encounter,
     aoi_sync_delta: pb::AoiSyncDelta,
     player_state: &PlayerState,
     is_bptimer_enabled: bool,
     player_cache: Option<&PlayerCacheMutex>,
+    meter_settings: &MeterSettings,
+    timestamp_ms: u128,
 ) -> Option<()> {
     let target_uuid = aoi_sync_delta.uuid;
     if target_uuid == 0 {
@@ -191,13 +240,18 @@ pub fn process_aoi_sync_delta(
     // Process attributes
     let target_entity_type = crate::protocol::pb::EEntityType::from(target_uuid);
     {
-        let target_entity = encounter
-            .entity_uid_to_entity
-            .entry(target_uid)
-            .or_insert_with(|| Entity {
-                entity_type: target_entity_type,
-                ..Default::default()
-            });
+        let target_entity = Arc::make_mut(
+            encounter
+                .entity_uid_to_entity
+                .entry(target_uid)
+                .or_insert_with(|| {
+                    Arc::new(Entity {
+                        entity_type: target_entity_type,
+                        ..Default::default()
+                    })
+                }),
+        );
+        let was_alive = target_entity.curr_hp != Some(0);
 
         if let Some(attrs_collection) = aoi_sync_delta.attrs {
             match target_entity_type {
@@ -219,6 +273,23 @@ pub fn process_aoi_sync_delta(
                 _ => {}
             }
         }
+
+        let is_boss = target_entity
+            .monster_id
+            .is_some_and(|id| MONSTER_NAMES_BOSS.contains_key(&id));
+        if is_boss
+            && was_alive
+            && target_entity.curr_hp == Some(0)
+            && encounter.time_fight_start_ms != 0
+        {
+            info!("Boss {target_uid} defeated");
+            encounter.is_boss_defeated = true;
+        }
+    }
+
+    // Entities are kept up to date while paused, only stats stop accumulating
+    if encounter.is_encounter_paused {
+        return Some(());
     }
 
     let Some(skill_effect) = aoi_sync_delta.skill_effects else {
@@ -227,11 +298,9 @@ pub fn process_aoi_sync_delta(
 
     // Process Damage
     for sync_damage_info in skill_effect.damages {
-        let is_boss = encounter
-            .entity_uid_to_entity
-            .get(&target_uid)
-            .and_then(|e| e.monster_id)
-            .is_some_and(|id| MONSTER_NAMES_BOSS.contains_key(&id));
+        let target_entity = encounter.entity_uid_to_entity.get(&target_uid);
+        let target_monster_id = target_entity.and_then(|e| e.monster_id);
+        let is_boss = target_monster_id.is_some_and(|id| MONSTER_NAMES_BOSS.contains_key(&id));
 
         let attacker_uuid = if sync_damage_info.top_summoner_id != 0 {
             sync_damage_info.top_summoner_id
@@ -241,13 +310,17 @@ pub fn process_aoi_sync_delta(
             continue; // Skip this damage packet if no attacker
         };
         let attacker_uid = entity::get_player_uid(attacker_uuid);
-        let attacker_entity = encounter
-            .entity_uid_to_entity
-            .entry(attacker_uid)
-            .or_insert_with(|| Entity {
-                entity_type: pb::EEntityType::from(attacker_uuid),
-                ..Default::default()
-            });
+        let attacker_entity = Arc::make_mut(
+            encounter
+                .entity_uid_to_entity
+                .entry(attacker_uid)
+                .or_insert_with(|| {
+                    Arc::new(Entity {
+                        entity_type: pb::EEntityType::from(attacker_uuid),
+                        ..Default::default()
+                    })
+                }),
+        );
 
         let skill_uid = sync_damage_info.owner_id;
         if skill_uid == 0 {
@@ -285,71 +358,47 @@ pub fn process_aoi_sync_delta(
             }
         }
 
-        // Skills
         let is_heal = sync_damage_info.r#type == pb::EDamageType::Heal as i32;
-        if is_heal {
-            let heal_skill = attacker_entity
-                .skill_uid_to_heal_stats
-                .entry(skill_uid)
-                .or_default();
-            process_stats(&sync_damage_info, heal_skill);
-            process_stats(&sync_damage_info, &mut attacker_entity.heal_stats); // update total entity heal stats
-            process_stats(&sync_damage_info, &mut encounter.heal_stats); // update total encounter heal stats
-        } else {
-            let dps_skill = attacker_entity
-                .skill_uid_to_dps_stats
-                .entry(skill_uid)
-                .or_default();
-            process_stats(&sync_damage_info, dps_skill);
-            process_stats(&sync_damage_info, &mut attacker_entity.dmg_stats); // update total entity dmg stats
-            process_stats(&sync_damage_info, &mut encounter.dmg_stats); // update total encounter dmg stats
-            if is_boss {
-                let skill_boss_only = attacker_entity
-                    .skill_uid_to_dps_stats_boss_only
-                    .entry(skill_uid)
-                    .or_default();
-                process_stats(&sync_damage_info, skill_boss_only);
-                process_stats(&sync_damage_info, &mut attacker_entity.dmg_stats_boss_only); // update total entity boss only dmg stats
-                process_stats(&sync_damage_info, &mut encounter.dmg_stats_boss_only); // update total encounter dmg stats
+
+        // Figure out timestamps, nothing counts until the fight start trigger is hit
+        if encounter.time_fight_start_ms == 0 {
+            let is_fight_start = !is_heal
+                && match meter_settings.fight_start_trigger {
+                    FightStartTrigger::Any => true,
+                    FightStartTrigger::LocalPlayer => attacker_uid == player_state.get_uid(),
+                    FightStartTrigger::Boss => is_boss,
+                    FightStartTrigger::Monster(monster_id) => target_monster_id == Some(monster_id),
+                };
+            if !is_fight_start {
+                continue;
             }
+            encounter.time_fight_start_ms = timestamp_ms;
+            encounter.line_id = player_state.get_line_id_opt();
+            encounter.level_map_id = player_state.get_level_map_id_opt();
         }
-    }
 
-    // Figure out timestamps
-    let timestamp_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
-    if encounter.time_fight_start_ms == 0 {
-        encounter.time_fight_start_ms = timestamp_ms;
+        let combat_event = CombatEvent {
+            timestamp_ms,
+            attacker_uid,
+            summon_uid: if sync_damage_info.top_summoner_id != 0 {
+                entity::get_player_uid(sync_damage_info.attacker_uuid)
+            } else {
+                0
+            },
+            target_uid,
+            skill_uid,
+            value: sync_damage_info.value,
+            lucky_value: sync_damage_info.lucky_value,
+            hp_lessened: sync_damage_info.hp_lessen_value,
+            is_heal,
+            is_crit: (sync_damage_info.type_flag & damage::CRIT_BIT) != 0,
+            is_dead: sync_damage_info.is_dead,
+        };
+        combat_log::record(encounter, &combat_event, &meter_settings.target_filters);
     }
-    encounter.time_last_combat_packet_ms = timestamp_ms;
     Some(())
 }
 
-fn process_stats(sync_damage_info: &pb::SyncDamageInfo, stats: &mut CombatStats) {
-    // Prefer lucky damage value if available (non-zero), otherwise use regular value
-    let actual_value = if sync_damage_info.lucky_value != 0 {
-        sync_damage_info.lucky_value
-    } else {
-        sync_damage_info.value
-    };
-
-    let is_lucky = sync_damage_info.lucky_value != 0;
-    let flag = sync_damage_info.type_flag;
-    let is_crit = (flag & damage::CRIT_BIT) != 0;
-    if is_crit {
-        stats.crit_hits += 1;
-        stats.crit_value += actual_value;
-    }
-    if is_lucky {
-        stats.lucky_hits += 1;
-        stats.lucky_value += actual_value;
-    }
-    stats.hits += 1;
-    stats.value += actual_value;
-}
-
 fn with_cache<F>(cache: Option<&PlayerCacheMutex>, f: F)
 where
     F: FnOnce(&mut crate::live::player_state::PlayerCache),
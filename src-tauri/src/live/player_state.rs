@@ -2,7 +2,7 @@ use crate::live::opcodes_models::class::{Class, ClassSpec};
 use std::collections::HashMap;
 use std::sync::Mutex;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct PlayerState {
     account_id: Option<String>,
     uid: Option<i64>,
@@ -73,6 +73,10 @@ impl PlayerState {
     pub fn get_line_id_opt(&self) -> Option<u32> {
         self.line_id
     }
+
+    pub fn get_level_map_id_opt(&self) -> Option<u32> {
+        self.level_map_id
+    }
 }
 
 #[derive(Debug, Default, Clone)]
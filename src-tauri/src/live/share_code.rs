@@ -0,0 +1,156 @@
+use crate::live::commands_models::{PlayerRow, PlayersWindow};
+use base64::{Engine as _, engine::general_purpose};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+
+// Bumped whenever `SharedPlayer` changes, old codes can't be read after that
+const SHARE_CODE_VERSION: u32 = 1;
+// Way more than any real code, stops pasted gzip bombs
+const MAX_DECODED_LEN: u64 = 64 * 1024;
+
+/// Meter table as sent in a share code, a JSON array to keep codes short
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct SharedPlayersWindow(u32, Vec<SharedPlayer>);
+
+/// name, class, class spec, total, per sec, %, crit rate, crit value rate, lucky rate
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct SharedPlayer(String, String, String, f64, f64, f64, f64, f64, f64);
+
+/// Encodes the first `top_n` players as gzip compressed JSON in URL safe base64, like
+/// `encode_module_data`. Only what the meter table needs is kept, rounded to shorten the code.
+pub fn encode_players_window(
+    players_window: &PlayersWindow,
+    top_n: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let round_pct = |pct: f64| (pct * 10.0).round() / 10.0;
+    let shared_players = players_window
+        .player_rows
+        .iter()
+        .take(top_n)
+        .map(|player_row| {
+            SharedPlayer(
+                player_row.name.clone(),
+                player_row.class_name.to_string(),
+                player_row.class_spec_name.to_string(),
+                player_row.total_value.round(),
+                player_row.value_per_sec.round(),
+                round_pct(player_row.value_pct),
+                round_pct(player_row.crit_rate),
+                round_pct(player_row.crit_value_rate),
+                round_pct(player_row.lucky_rate),
+            )
+        })
+        .collect();
+    let json = serde_json::to_string(&SharedPlayersWindow(SHARE_CODE_VERSION, shared_players))?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(json.as_bytes())?;
+    let compressed = encoder.finish()?;
+    Ok(general_purpose::URL_SAFE_NO_PAD.encode(&compressed))
+}
+
+/// Turns a share code back into a meter table. Players get their rank as uid since the real
+/// uids are not shared, and none of them is the local player. Fields that aren't shared are left
+/// at 0, except the ability score which is -1 so it's hidden.
+pub fn decode_players_window(code: &str) -> Result<PlayersWindow, Box<dyn std::error::Error>> {
+    let compressed = general_purpose::URL_SAFE_NO_PAD.decode(code.trim())?;
+    let mut json = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .take(MAX_DECODED_LEN + 1)
+        .read_to_string(&mut json)?;
+    if json.len() as u64 > MAX_DECODED_LEN {
+        return Err("Share code is too large".into());
+    }
+    let SharedPlayersWindow(version, shared_players) = serde_json::from_str(&json)?;
+    if version != SHARE_CODE_VERSION {
+        return Err(format!("Unsupported share code version {version}").into());
+    }
+
+    let player_rows = shared_players
+        .into_iter()
+        .enumerate()
+        .map(|(i, shared_player)| {
+            let SharedPlayer(
+                name,
+                class_name,
+                class_spec_name,
+                total_value,
+                value_per_sec,
+                value_pct,
+                crit_rate,
+                crit_value_rate,
+                lucky_rate,
+            ) = shared_player;
+            PlayerRow {
+                uid: (i + 1) as f64,
+                name,
+                class_name: class_name.into(),
+                class_spec_name: class_spec_name.into(),
+                total_value,
+                value_per_sec,
+                value_pct,
+                crit_rate,
+                crit_value_rate,
+                lucky_rate,
+                ability_score: -1.0,
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(PlayersWindow {
+        top_value: player_rows
+            .iter()
+            .map(|player_row| player_row.total_value)
+            .fold(0.0, f64::max),
+        local_player_uid: -1.0,
+        player_rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live::commands_models::{PlayerRow, PlayersWindow};
+    use crate::live::share_code::{decode_players_window, encode_players_window};
+
+    #[test]
+    fn test_share_code() {
+        let players_window = PlayersWindow {
+            player_rows: (0..12)
+                .map(|i| PlayerRow {
+                    uid: 1000.0 + f64::from(i),
+                    name: format!("Player {i}"),
+                    class_name: "Frost Mage".into(),
+                    total_value: 120_000.0 - f64::from(i) * 1000.0,
+                    value_per_sec: 4000.4,
+                    value_pct: 8.333,
+                    crit_rate: 25.06,
+                    ..Default::default()
+                })
+                .collect(),
+            local_player_uid: 1000.0,
+            top_value: 120_000.0,
+        };
+
+        let code = encode_players_window(&players_window, 10).unwrap();
+        assert!(
+            code.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        let decoded = decode_players_window(&format!(" {code}\n")).unwrap();
+        assert_eq!(decoded.player_rows.len(), 10);
+        assert_eq!(decoded.top_value, 120_000.0);
+        assert_eq!(decoded.local_player_uid, -1.0);
+        let player_row = &decoded.player_rows[1];
+        assert_eq!(player_row.uid, 2.0);
+        assert_eq!(player_row.name, "Player 1");
+        assert_eq!(player_row.class_name, "Frost Mage");
+        assert_eq!(player_row.value_per_sec, 4000.0);
+        assert_eq!(player_row.value_pct, 8.3);
+        assert_eq!(player_row.crit_rate, 25.1);
+        assert_eq!(player_row.ability_score, -1.0);
+
+        assert!(decode_players_window("not a share code").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::live::commands_models::SavedEncounter;
+use crate::live::encounter_history::EncounterEndReason;
+use crate::live::opcodes_models::MONSTER_NAMES_BOSS;
+use log::{error, info, warn};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// Players listed in a summary, Discord allows at most 25 embed fields
+const SUMMARY_TOP_PLAYERS: usize = 10;
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq,
+)]
+pub enum WebhookFormat {
+    /// `EncounterSummary` as is
+    #[default]
+    #[serde(rename = "JSON")]
+    Json,
+    /// Discord embed made from the `EncounterSummary`
+    Discord,
+}
+
+/// Opt-in sink that posts a summary of every finished encounter to a user defined url
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub format: WebhookFormat,
+}
+
+pub type WebhookConfigMutex = Mutex<WebhookConfig>;
+
+/// Body of `WebhookFormat::Json` webhooks
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncounterSummary {
+    pub encounter_id: String,
+    pub name: String,
+    pub end_reason: EncounterEndReason,
+    pub boss_name: Option<String>, // boss that took the most damage
+    pub time_fight_start_ms: f64,
+    pub duration_secs: f64,
+    pub total_dmg: f64,
+    pub total_dps: f64,
+    pub deaths: u32, // all players
+    pub top_players: Vec<SummaryPlayer>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryPlayer {
+    pub uid: f64,
+    pub name: String,
+    pub class_name: String,
+    pub class_spec_name: String,
+    pub ability_score: f64,
+    pub dmg: f64,
+    pub dps: f64,
+    pub dmg_pct: f64,
+    pub deaths: u32,
+}
+
+struct WebhookTask {
+    url: String,
+    body: serde_json::Value,
+}
+
+static WEBHOOK_SENDER: OnceLock<Sender<WebhookTask>> = OnceLock::new();
+
+pub fn get_encounter_summary(
+    saved_encounter: &SavedEncounter,
+    end_reason: EncounterEndReason,
+) -> EncounterSummary {
+    let get_deaths = |uid: f64| {
+        saved_encounter
+            .entities
+            .iter()
+            .find(|saved_entity| saved_entity.uid == uid)
+            .map_or(0, |saved_entity| saved_entity.deaths)
+    };
+    let players = &saved_encounter.dps_players.player_rows;
+    EncounterSummary {
+        encounter_id: saved_encounter.id.clone(),
+        name: saved_encounter.name.clone(),
+        end_reason,
+        boss_name: get_main_boss_name(saved_encounter),
+        time_fight_start_ms: saved_encounter.time_fight_start_ms,
+        duration_secs: saved_encounter.header_info.elapsed_ms / 1000.0,
+        total_dmg: saved_encounter.header_info.total_dmg,
+        total_dps: saved_encounter.header_info.total_dps,
+        deaths: players
+            .iter()
+            .map(|player_row| get_deaths(player_row.uid))
+            .sum(),
+        top_players: players
+            .iter()
+            .take(SUMMARY_TOP_PLAYERS)
+            .map(|player_row| SummaryPlayer {
+                uid: player_row.uid,
+                name: player_row.name.clone(),
+                class_name: player_row.class_name.to_string(),
+                class_spec_name: player_row.class_spec_name.to_string(),
+                ability_score: player_row.ability_score,
+                dmg: player_row.total_value,
+                dps: player_row.value_per_sec,
+                dmg_pct: player_row.value_pct,
+                deaths: get_deaths(player_row.uid),
+            })
+            .collect(),
+    }
+}
+
+// Entities are sorted by damage taken, so the first boss is the main one
+fn get_main_boss_name(saved_encounter: &SavedEncounter) -> Option<String> {
+    let monster_id = saved_encounter
+        .entities
+        .iter()
+        .find(|saved_entity| saved_entity.is_boss)?
+        .monster_id?;
+    MONSTER_NAMES_BOSS.get(&monster_id).cloned()
+}
+
+fn get_discord_body(summary: &EncounterSummary) -> serde_json::Value {
+    let title = summary.boss_name.as_deref().unwrap_or("Encounter");
+    let duration_secs = summary.duration_secs as u64;
+    let description = format!(
+        "{:?} after {}:{:02} | Raid DPS {} | Deaths {}",
+        summary.end_reason,
+        duration_secs / 60,
+        duration_secs % 60,
+        abbreviate_number(summary.total_dps),
+        summary.deaths
+    );
+    let fields = summary
+        .top_players
+        .iter()
+        .enumerate()
+        .map(|(i, player)| {
+            serde_json::json!({
+                "name": format!("{}. {} ({})", i + 1, player.name, player.class_spec_name),
+                "value": format!(
+                    "{} DPS | {:.1}% | {} deaths",
+                    abbreviate_number(player.dps),
+                    player.dmg_pct,
+                    player.deaths
+                ),
+                "inline": false,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "embeds": [{
+            "title": title,
+            "description": description,
+            "fields": fields,
+            "footer": { "text": summary.name },
+        }]
+    })
+}
+
+// Same abbreviations as the meter window
+fn abbreviate_number(n: f64) -> String {
+    match n {
+        n if n >= 1e12 => format!("{:.1}t", n / 1e12),
+        n if n >= 1e9 => format!("{:.1}b", n / 1e9),
+        n if n >= 1e6 => format!("{:.1}m", n / 1e6),
+        n if n >= 1e3 => format!("{:.1}k", n / 1e3),
+        n => format!("{n:.0}"),
+    }
+}
+
+/// Queues the summary for delivery, failed deliveries are retried with backoff in the background
+pub fn send_encounter_summary(webhook_config: &WebhookConfig, summary: &EncounterSummary) {
+    if !webhook_config.enabled || webhook_config.url.trim().is_empty() {
+        return;
+    }
+    let body = match webhook_config.format {
+        WebhookFormat::Json => match serde_json::to_value(summary) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize encounter summary: {e}");
+                return;
+            }
+        },
+        WebhookFormat::Discord => get_discord_body(summary),
+    };
+    let task = WebhookTask {
+        url: webhook_config.url.trim().to_string(),
+        body,
+    };
+    if get_webhook_sender().send(task).is_err() {
+        warn!("Webhook worker is not running, encounter summary dropped");
+    }
+}
+
+fn create_client() -> reqwest::blocking::Client {
+    let user_agent = format!("BPSR-Logs/{}", env!("CARGO_PKG_VERSION"));
+    reqwest::blocking::Client::builder()
+        .user_agent(&user_agent)
+        .tls_backend_rustls()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_else(|_| reqwest::blocking::Client::new())
+}
+
+fn get_webhook_sender() -> &'static Sender<WebhookTask> {
+    WEBHOOK_SENDER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<WebhookTask>();
+
+        std::thread::spawn(move || {
+            let client = create_client();
+            while let Ok(task) = rx.recv() {
+                deliver(&client, &task);
+            }
+        });
+
+        tx
+    })
+}
+
+fn deliver(client: &reqwest::blocking::Client, task: &WebhookTask) {
+    let mut retry_delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        let retry_after = match client.post(&task.url).json(&task.body).send() {
+            Ok(resp) if resp.status().is_success() => {
+                info!("Encounter summary sent to webhook");
+                return;
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                // Other client errors won't go away by sending the same request again
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    error!("Webhook rejected encounter summary: {status}");
+                    return;
+                }
+                warn!("Webhook attempt {attempt}/{MAX_DELIVERY_ATTEMPTS} failed: {status}");
+                resp.headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .map(|secs| Duration::from_secs_f64(secs.clamp(0.0, 60.0)))
+            }
+            Err(e) => {
+                warn!("Webhook attempt {attempt}/{MAX_DELIVERY_ATTEMPTS} failed: {e}");
+                None
+            }
+        };
+        if attempt == MAX_DELIVERY_ATTEMPTS {
+            break;
+        }
+        std::thread::sleep(retry_after.unwrap_or(retry_delay));
+        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+    }
+    error!("Giving up on encounter summary after {MAX_DELIVERY_ATTEMPTS} attempts");
+}
@@ -3,4 +3,5 @@
 pub mod opcodes;
 pub mod packet_capture;
 mod packet_process;
+pub mod pipeline_metrics;
 pub mod utils;
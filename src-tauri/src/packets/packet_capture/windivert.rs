@@ -1,7 +1,8 @@
 use crate::packets;
 use crate::packets::opcodes::Pkt;
 use crate::packets::packet_process::process_packet;
-use crate::packets::utils::{BinaryReader, Server, TCPReassembler};
+use crate::packets::pipeline_metrics::{PIPELINE_METRICS, increment};
+use crate::packets::utils::{BinaryReader, Server, TCPReassembler, get_timestamp_ms};
 use etherparse::NetSlice::Ipv4;
 use etherparse::SlicedPacket;
 use etherparse::TransportSlice::Tcp;
@@ -11,22 +12,67 @@ use std::sync::OnceLock;
 use tokio::sync::watch;
 use windivert::WinDivert;
 use windivert::prelude::WinDivertFlags;
+use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
 
 // Global sender for restart signal
 static RESTART_SENDER: OnceLock<watch::Sender<bool>> = OnceLock::new();
 
-fn send_server_change_info(packet_sender: &tokio::sync::mpsc::Sender<(Pkt, Vec<u8>)>) {
-    let _ = packet_sender.try_send((Pkt::ServerChangeInfo, Vec::new()));
+fn send_server_change_info(
+    packet_sender: &tokio::sync::mpsc::Sender<(Pkt, Vec<u8>, u128)>,
+    capture_timestamp_ms: u128,
+) {
+    if packet_sender
+        .try_send((Pkt::ServerChangeInfo, Vec::new(), capture_timestamp_ms))
+        .is_err()
+    {
+        increment(&PIPELINE_METRICS.channel_drops);
+    }
+}
+
+/// Converts WinDivert event timestamps (`QueryPerformanceCounter` ticks) to the unix epoch ms of
+/// `get_timestamp_ms`, anchored once so that both clocks agree.
+struct EventClock {
+    anchor_ticks: i64,
+    anchor_ms: u128,
+    ticks_per_sec: i64,
+}
+
+impl EventClock {
+    fn new() -> Option<Self> {
+        let mut ticks_per_sec = 0;
+        let mut anchor_ticks = 0;
+        // SAFETY: both calls only write to the given i64
+        let is_ok = unsafe {
+            QueryPerformanceFrequency(&mut ticks_per_sec) != 0
+                && QueryPerformanceCounter(&mut anchor_ticks) != 0
+        };
+        (is_ok && ticks_per_sec > 0).then(|| Self {
+            anchor_ticks,
+            anchor_ms: get_timestamp_ms(),
+            ticks_per_sec,
+        })
+    }
+
+    fn get_timestamp_ms(&self, event_ticks: i64) -> Option<u128> {
+        if event_ticks <= 0 {
+            return None; // not stamped by the driver
+        }
+        let elapsed_ms =
+            i128::from(event_ticks - self.anchor_ticks) * 1000 / i128::from(self.ticks_per_sec);
+        u128::try_from(self.anchor_ms as i128 + elapsed_ms).ok()
+    }
 }
 
 // Delay between handle cleanup and recreation to allow kernel cleanup
 const HANDLE_CLEANUP_DELAY_MS: u64 = 500;
 const MAX_SUBNET_CONNECTIONS: usize = 16;
 
-pub fn start_capture() -> tokio::sync::mpsc::Receiver<(packets::opcodes::Pkt, Vec<u8>)> {
+pub fn start_capture() -> tokio::sync::mpsc::Receiver<(packets::opcodes::Pkt, Vec<u8>, u128)> {
     const PACKET_CHANNEL_CAPACITY: usize = 256;
     let (packet_sender, packet_receiver) =
-        tokio::sync::mpsc::channel::<(packets::opcodes::Pkt, Vec<u8>)>(PACKET_CHANNEL_CAPACITY);
+        tokio::sync::mpsc::channel::<(packets::opcodes::Pkt, Vec<u8>, u128)>(
+            PACKET_CHANNEL_CAPACITY,
+        );
     let (restart_sender, mut restart_receiver) = watch::channel(false);
     RESTART_SENDER.set(restart_sender.clone()).ok();
     tauri::async_runtime::spawn(async move {
@@ -46,7 +92,7 @@ pub fn start_capture() -> tokio::sync::mpsc::Receiver<(packets::opcodes::Pkt, Ve
 }
 
 async fn read_packets(
-    packet_sender: &tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>)>,
+    packet_sender: &tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>, u128)>,
     restart_receiver: &mut watch::Receiver<bool>,
 ) {
     let windivert = match WinDivert::network(
@@ -69,10 +115,20 @@ async fn read_packets(
     let mut tcp_reassembler: TCPReassembler = TCPReassembler::new();
     let mut game_subnet: Option<[u8; 2]> = None;
     let mut subnet_reassemblers: HashMap<Server, TCPReassembler> = HashMap::new();
+    let event_clock = EventClock::new();
+    if event_clock.is_none() {
+        warn!("No performance counter, packets are timestamped when they are read instead");
+    }
 
     // Note: windivert.recv() is blocking, so we can't check restart signal while it's blocking.
     // The restart will be detected after the next packet is received.
     while let Ok(packet) = windivert.recv(Some(&mut windivert_buffer)) {
+        // The driver stamps packets when they are captured, so time spent queued isn't counted
+        let capture_timestamp_ms = event_clock
+            .as_ref()
+            .and_then(|event_clock| event_clock.get_timestamp_ms(packet.address.event_timestamp()))
+            .unwrap_or_else(get_timestamp_ms);
+        increment(&PIPELINE_METRICS.frames_captured);
         let Ok(network_slices) = SlicedPacket::from_ip(packet.data.as_ref()) else {
             continue; // if it's not ip, go next packet
         };
@@ -137,7 +193,10 @@ async fn read_packets(
                                                         + tcp_payload_reader.len(),
                                                     &mut subnet_reassemblers,
                                                 );
-                                                send_server_change_info(packet_sender);
+                                                send_server_change_info(
+                                                    packet_sender,
+                                                    capture_timestamp_ms,
+                                                );
                                                 detected = true;
                                                 break;
                                             }
@@ -182,7 +241,7 @@ async fn read_packets(
                         tcp_packet.sequence_number() as usize + tcp_payload.len(),
                         &mut subnet_reassemblers,
                     );
-                    send_server_change_info(packet_sender);
+                    send_server_change_info(packet_sender, capture_timestamp_ms);
                     detected = true;
                 }
             }
@@ -197,7 +256,13 @@ async fn read_packets(
                             }
                         }
                         if let Some(reassembler) = subnet_reassemblers.get_mut(&curr_server) {
-                            reassemble_and_process(reassembler, &tcp_packet, packet_sender, true);
+                            reassemble_and_process(
+                                reassembler,
+                                &tcp_packet,
+                                packet_sender,
+                                true,
+                                capture_timestamp_ms,
+                            );
                         }
                     }
                 }
@@ -206,7 +271,13 @@ async fn read_packets(
         }
 
         // Primary server reassembly
-        reassemble_and_process(&mut tcp_reassembler, &tcp_packet, packet_sender, false);
+        reassemble_and_process(
+            &mut tcp_reassembler,
+            &tcp_packet,
+            packet_sender,
+            false,
+            capture_timestamp_ms,
+        );
 
         if *restart_receiver.borrow() {
             info!("WinDivert restart requested during packet processing, closing handle");
@@ -243,8 +314,9 @@ fn update_known_server(
 fn reassemble_and_process(
     reassembler: &mut TCPReassembler,
     tcp_packet: &etherparse::TcpSlice<'_>,
-    packet_sender: &tokio::sync::mpsc::Sender<(Pkt, Vec<u8>)>,
+    packet_sender: &tokio::sync::mpsc::Sender<(Pkt, Vec<u8>, u128)>,
     clear_on_malformed: bool,
+    capture_timestamp_ms: u128,
 ) {
     if tcp_packet.payload().is_empty() {
         return;
@@ -298,6 +370,7 @@ fn reassemble_and_process(
         const MIN_PACKET_SIZE: u32 = 6;
         const MAX_PACKET_SIZE: u32 = 10 * 1024 * 1024;
         if packet_size < MIN_PACKET_SIZE || packet_size > MAX_PACKET_SIZE {
+            increment(&PIPELINE_METRICS.reassembly_drops);
             if clear_on_malformed {
                 reassembler._data.clear();
                 break;
@@ -315,9 +388,10 @@ fn reassemble_and_process(
         let (left, right) = reassembler._data.split_at(packet_size as usize);
         let packet = left.to_vec();
         reassembler._data = right.to_vec();
+        increment(&PIPELINE_METRICS.packets_reassembled);
         let sender = packet_sender.clone();
         tauri::async_runtime::spawn(async move {
-            process_packet(BinaryReader::from(packet), sender).await;
+            process_packet(BinaryReader::from(packet), sender, capture_timestamp_ms).await;
         });
     }
 }
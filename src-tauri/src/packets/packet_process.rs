@@ -1,22 +1,26 @@
 use crate::packets;
 use crate::packets::opcodes::{FragmentType, Pkt};
+use crate::packets::pipeline_metrics::{PIPELINE_METRICS, increment};
 use crate::packets::utils::BinaryReader;
 use log::debug;
 
 pub async fn process_packet(
     mut packets_reader: BinaryReader,
-    packet_sender: tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>)>,
+    packet_sender: tokio::sync::mpsc::Sender<(packets::opcodes::Pkt, Vec<u8>, u128)>,
+    capture_timestamp_ms: u128,
 ) {
     while packets_reader.remaining() > 0 {
         let packet_size = match packets_reader.peek_u32() {
             Ok(sz) => sz,
             Err(e) => {
                 debug!("Malformed packet: failed to peek_u32: {e}");
+                increment(&PIPELINE_METRICS.decode_errors);
                 break;
             }
         };
         if packet_size < 6 {
             debug!("Malformed packet: packet_size < 6");
+            increment(&PIPELINE_METRICS.decode_errors);
             break;
         }
 
@@ -24,17 +28,20 @@ pub async fn process_packet(
             Ok(bytes) => BinaryReader::from(bytes),
             Err(e) => {
                 debug!("Malformed packet: failed to read_bytes: {e}");
+                increment(&PIPELINE_METRICS.decode_errors);
                 continue;
             }
         };
         if reader.read_u32().is_err() {
             debug!("Malformed packet: failed to skip u32");
+            increment(&PIPELINE_METRICS.decode_errors);
             continue;
         }
         let packet_type = match reader.read_u16() {
             Ok(pt) => pt,
             Err(e) => {
                 debug!("Malformed packet: failed to read_u16: {e}");
+                increment(&PIPELINE_METRICS.decode_errors);
                 continue;
             }
         };
@@ -47,6 +54,7 @@ pub async fn process_packet(
                     Ok(su) => su,
                     Err(e) => {
                         debug!("Malformed Notify: failed to read_u64 service_uuid: {e}");
+                        increment(&PIPELINE_METRICS.decode_errors);
                         continue;
                     }
                 };
@@ -54,6 +62,7 @@ pub async fn process_packet(
                     Ok(sid) => sid,
                     Err(e) => {
                         debug!("Malformed Notify: failed to read_u32 stub_id: {e}");
+                        increment(&PIPELINE_METRICS.decode_errors);
                         continue;
                     }
                 };
@@ -61,6 +70,7 @@ pub async fn process_packet(
                     Ok(mid) => mid,
                     Err(e) => {
                         debug!("Malformed Notify: failed to read_u32 method_id: {e}");
+                        increment(&PIPELINE_METRICS.decode_errors);
                         continue;
                     }
                 };
@@ -72,6 +82,7 @@ pub async fn process_packet(
                         Ok(decoded) => tcp_fragment_vec = decoded,
                         Err(e) => {
                             debug!("Notify: zstd decompression failed: {e}");
+                            increment(&PIPELINE_METRICS.decode_errors);
                             continue;
                         }
                     }
@@ -82,10 +93,15 @@ pub async fn process_packet(
                     && method_id_raw == crate::protocol::constants::SOCIAL_NTF_NOTIFY_METHOD_ID
                 {
                     if let Err(err) = packet_sender
-                        .send((Pkt::NotifySocialData, tcp_fragment_vec))
+                        .send((
+                            Pkt::NotifySocialData,
+                            tcp_fragment_vec,
+                            capture_timestamp_ms,
+                        ))
                         .await
                     {
                         debug!("Failed to send SocialNtf packet: {err}");
+                        increment(&PIPELINE_METRICS.channel_drops);
                     }
                     continue;
                 }
@@ -95,10 +111,15 @@ pub async fn process_packet(
                     && method_id_raw == crate::protocol::constants::ENTER_WORLD_NOTIFY_METHOD_ID
                 {
                     if let Err(err) = packet_sender
-                        .send((Pkt::NotifyEnterWorld, tcp_fragment_vec))
+                        .send((
+                            Pkt::NotifyEnterWorld,
+                            tcp_fragment_vec,
+                            capture_timestamp_ms,
+                        ))
                         .await
                     {
                         debug!("Failed to send WorldLoginNtf packet: {err}");
+                        increment(&PIPELINE_METRICS.channel_drops);
                     }
                     continue;
                 }
@@ -115,8 +136,12 @@ pub async fn process_packet(
                     }
                 };
 
-                if let Err(err) = packet_sender.send((method_id, tcp_fragment_vec)).await {
+                if let Err(err) = packet_sender
+                    .send((method_id, tcp_fragment_vec, capture_timestamp_ms))
+                    .await
+                {
                     debug!("Failed to send packet: {err}");
+                    increment(&PIPELINE_METRICS.channel_drops);
                 }
             }
             FragmentType::FrameDown => {
@@ -124,6 +149,7 @@ pub async fn process_packet(
                     Ok(sid) => sid,
                     Err(e) => {
                         debug!("FrameDown: failed to read_u32 server_sequence_id: {e}");
+                        increment(&PIPELINE_METRICS.decode_errors);
                         continue;
                     }
                 };
@@ -140,6 +166,7 @@ pub async fn process_packet(
                         }
                         Err(e) => {
                             debug!("FrameDown: zstd decompression failed: {e}");
+                            increment(&PIPELINE_METRICS.decode_errors);
                             continue;
                         }
                     }
@@ -149,6 +176,7 @@ pub async fn process_packet(
             }
             _ => {
                 debug!("Unknown fragment type: {msg_type_id}");
+                increment(&PIPELINE_METRICS.decode_errors);
                 continue;
             }
         }
@@ -163,12 +191,12 @@ mod tests {
     #[tokio::test]
     async fn test_add() {
         use std::fs;
-        let (packet_sender, _) = tokio::sync::mpsc::channel::<(Pkt, Vec<u8>)>(1);
+        let (packet_sender, _) = tokio::sync::mpsc::channel::<(Pkt, Vec<u8>, u128)>(1);
         let filename = "src/packets/test_add_packet.json";
         let v: Vec<u8> = serde_json::from_str(
             &fs::read_to_string(filename).expect(&format!("Failed to open {filename}")),
         )
         .expect("Invalid JSON in test_packet.json");
-        process_packet(BinaryReader::from(v), packet_sender).await;
+        process_packet(BinaryReader::from(v), packet_sender, 0).await;
     }
 }
@@ -0,0 +1,28 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Health counters of the capture pipeline since startup, exported by the local API `/metrics`
+pub struct PipelineMetrics {
+    pub frames_captured: AtomicU64, // every frame read from the capture driver
+    pub packets_reassembled: AtomicU64, // game packets cut from the reassembled TCP stream
+    pub messages_processed: AtomicU64, // decoded messages received by the encounter processing
+    pub decode_errors: AtomicU64, // malformed fragments, failed decompression or protobuf decoding
+    pub reassembly_drops: AtomicU64, // stream data thrown away because of an invalid packet size
+    pub channel_drops: AtomicU64, // messages lost because the processing channel was full or closed
+}
+
+pub static PIPELINE_METRICS: PipelineMetrics = PipelineMetrics {
+    frames_captured: AtomicU64::new(0),
+    packets_reassembled: AtomicU64::new(0),
+    messages_processed: AtomicU64::new(0),
+    decode_errors: AtomicU64::new(0),
+    reassembly_drops: AtomicU64::new(0),
+    channel_drops: AtomicU64::new(0),
+};
+
+pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
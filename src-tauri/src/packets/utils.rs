@@ -1,8 +1,27 @@
 use byteorder::{BigEndian, ReadBytesExt};
 use std::collections::BTreeMap;
 use std::io::{Cursor, Read};
+use std::sync::LazyLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{fmt, io};
 
+// Wall clock time at the first call, later timestamps are measured from it with a monotonic clock
+static CLOCK_ANCHOR: LazyLock<(Instant, u128)> = LazyLock::new(|| {
+    let epoch_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    (Instant::now(), epoch_ms)
+});
+
+/// Milliseconds since the unix epoch that never go backwards, even if the system clock is adjusted.
+/// Packets without a driver timestamp are stamped with this when they are read, anything without
+/// a capture time uses it too.
+pub fn get_timestamp_ms() -> u128 {
+    let (anchor_instant, anchor_epoch_ms) = *CLOCK_ANCHOR;
+    anchor_epoch_ms + anchor_instant.elapsed().as_millis()
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Server {
     src_addr: [u8; 4],
@@ -2,6 +2,7 @@
 // This file has been generated by Tauri Specta. Do not edit this file manually.
 
 import { invoke as __TAURI_INVOKE } from "@tauri-apps/api/core";
+import * as __TAURI_EVENT from "@tauri-apps/api/event";
 
 /** Commands */
 export const commands = {
@@ -12,22 +13,131 @@ export const commands = {
 	getDpsBossOnlySkillWindow: (playerUidStr: string) => typedError<SkillsWindow, string>(__TAURI_INVOKE("get_dps_boss_only_skill_window", { playerUidStr })),
 	getHealPlayerWindow: () => __TAURI_INVOKE<PlayersWindow>("get_heal_player_window"),
 	getHealSkillWindow: (playerUidStr: string) => typedError<SkillsWindow, string>(__TAURI_INVOKE("get_heal_skill_window", { playerUidStr })),
+	getDpsFilteredPlayerWindow: (filterId: number) => __TAURI_INVOKE<PlayersWindow>("get_dps_filtered_player_window", { filterId }),
+	getDpsFilteredSkillWindow: (filterId: number, playerUidStr: string) => typedError<SkillsWindow, string>(__TAURI_INVOKE("get_dps_filtered_skill_window", { filterId, playerUidStr })),
+	getDpsTimelineWindow: (playerUidStrs: string[], smoothingSecs: number | null) => typedError<TimelineWindow, string>(__TAURI_INVOKE("get_dps_timeline_window", { playerUidStrs, smoothingSecs })),
+	getDpsBossOnlyTimelineWindow: (playerUidStrs: string[], smoothingSecs: number | null) => typedError<TimelineWindow, string>(__TAURI_INVOKE("get_dps_boss_only_timeline_window", { playerUidStrs, smoothingSecs })),
+	getHealTimelineWindow: (playerUidStrs: string[], smoothingSecs: number | null) => typedError<TimelineWindow, string>(__TAURI_INVOKE("get_heal_timeline_window", { playerUidStrs, smoothingSecs })),
+	getDpsFilteredTimelineWindow: (filterId: number, playerUidStrs: string[], smoothingSecs: number | null) => typedError<TimelineWindow, string>(__TAURI_INVOKE("get_dps_filtered_timeline_window", { filterId, playerUidStrs, smoothingSecs })),
+	/**
+	 * One command for any combination of stat kind, targets, attackers and time range.
+	 * Returns the skills of `query.player_uid_str` if set, otherwise all players.
+	 */
+	queryEncounter: (query: EncounterQuery) => typedError<QueryResult, string>(__TAURI_INVOKE("query_encounter", { query })),
 	resetEncounter: () => __TAURI_INVOKE<void>("reset_encounter"),
+	getResetSnapshots: () => __TAURI_INVOKE<ResetSnapshotSummary[]>("get_reset_snapshots"),
+	/**
+	 * Brings back the encounter cleared by a reset, `index` 0 being the most recent reset.
+	 * The current encounter takes its place in the snapshots so the restore can be undone too.
+	 */
+	restoreEncounter: (index: number) => typedError<null, string>(__TAURI_INVOKE("restore_encounter", { index })),
 	togglePauseEncounter: () => __TAURI_INVOKE<void>("toggle_pause_encounter"),
 	hardReset: () => __TAURI_INVOKE<void>("hard_reset"),
 	quitApp: () => __TAURI_INVOKE<void>("quit_app"),
 	getTestPlayerWindow: () => __TAURI_INVOKE<PlayersWindow>("get_test_player_window"),
 	getTestSkillWindow: (playerUid: string) => typedError<SkillsWindow, string>(__TAURI_INVOKE("get_test_skill_window", { playerUid })),
 	setBptimerEnabled: (enabled: boolean) => __TAURI_INVOKE<void>("set_bptimer_enabled", { enabled }),
+	setRollingWindowSecs: (secs: number) => __TAURI_INVOKE<void>("set_rolling_window_secs", { secs }),
+	setLiveUpdateIntervalMs: (ms: number) => __TAURI_INVOKE<void>("set_live_update_interval_ms", { ms }),
+	setLocalApiConfig: (config: LocalApiConfig) => __TAURI_INVOKE<void>("set_local_api_config", { config }),
+	setWebhookConfig: (config: WebhookConfig) => __TAURI_INVOKE<void>("set_webhook_config", { config }),
+	setEncounterIdleTimeoutSecs: (secs: number) => __TAURI_INVOKE<void>("set_encounter_idle_timeout_secs", { secs }),
+	setFightStartTrigger: (trigger: FightStartTrigger) => __TAURI_INVOKE<void>("set_fight_start_trigger", { trigger }),
+	/**
+	 * The current encounter is recomputed from its combat log, so new filters cover the whole fight
+	 */
+	setTargetFilters: (targetFilters: TargetFilter[]) => __TAURI_INVOKE<void>("set_target_filters", { targetFilters }),
+	getEncounterHistory: () => typedError<SavedEncounterSummary[], string>(__TAURI_INVOKE("get_encounter_history")),
+	loadEncounter: (id: string) => typedError<SavedEncounter, string>(__TAURI_INVOKE("load_encounter", { id })),
+	renameEncounter: (id: string, name: string) => typedError<null, string>(__TAURI_INVOKE("rename_encounter", { id, name })),
+	deleteEncounter: (id: string) => typedError<null, string>(__TAURI_INVOKE("delete_encounter", { id })),
+	/**
+	 * Writes the saved encounter `id`, or the current one if `id` is None, to a JSON file in the
+	 * Downloads folder and returns its path. See `EncounterExport` for the schema.
+	 */
+	exportEncounter: (id: string | null) => typedError<string, string>(__TAURI_INVOKE("export_encounter", { id })),
+	/**
+	 * Writes the player and skill tables of the saved encounter `id`, or the current one if `id`
+	 * is None, to CSV files in a new folder in Downloads and returns the folder path.
+	 * There is one players and one skills file each for dmg, boss-only dmg and heal.
+	 */
+	exportEncounterCsv: (id: string | null) => typedError<string, string>(__TAURI_INVOKE("export_encounter_csv", { id })),
+	/**
+	 * Short code of the first `top_n` players of `players_window` that can be pasted in chat
+	 */
+	encodeShareCode: (playersWindow: PlayersWindow, topN: number) => typedError<string, string>(__TAURI_INVOKE("encode_share_code", { playersWindow, topN })),
+	decodeShareCode: (code: string) => typedError<PlayersWindow, string>(__TAURI_INVOKE("decode_share_code", { code })),
 	extractModulesFromLocalPlayer: () => typedError<ModuleOptimizerExportResult, string>(__TAURI_INVOKE("extract_modules_from_local_player")),
 };
 
+/** Events */
+export const events = makeEvents<{
+	headerInfoUpdated: HeaderInfoUpdated,
+	dpsPlayersUpdated: DpsPlayersUpdated,
+	dpsBossOnlyPlayersUpdated: DpsBossOnlyPlayersUpdated,
+	healPlayersUpdated: HealPlayersUpdated,
+}>({
+	headerInfoUpdated: "header-info-updated",
+	dpsPlayersUpdated: "dps-players-updated",
+	dpsBossOnlyPlayersUpdated: "dps-boss-only-players-updated",
+	healPlayersUpdated: "heal-players-updated",
+});
+
 /* Types */
+export type DpsBossOnlyPlayersUpdated = PlayersWindow;
+
+export type DpsPlayersUpdated = PlayersWindow;
+
+export type EncounterEndReason = "IdleTimeout" | "BossDefeated" | "Reset" | "ServerChange" | "LineChange";
+
+export type EncounterQuery = {
+	statKind: QueryStatKind,
+	targets: QueryTargets,
+	attackerUidStrs: string[],
+	startMs: number | null,
+	endMs: number | null,
+	mergeSummons: boolean,
+	playerUidStr: string | null,
+};
+
+/**
+ * What has to happen before the fight clock starts, damage before that is ignored
+ */
+export type FightStartTrigger = "Any" | "LocalPlayer" | "Boss" | { Monster: number };
+
 export type HeaderInfo = {
 	totalDps: number | null,
 	totalDmg: number | null,
 	elapsedMs: number | null,
 	timeLastCombatPacketMs: number | null,
+	rollingDps: number | null,
+	rollingWindowSecs: number | null,
+};
+
+export type HeaderInfoUpdated = HeaderInfo;
+
+export type HealPlayersUpdated = PlayersWindow;
+
+export type HitDistributionRow = {
+	hits: number | null,
+	min: number | null,
+	max: number | null,
+	mean: number | null,
+	p50: number | null,
+	p90: number | null,
+	p99: number | null,
+};
+
+/**
+ * Settings of the optional server on 127.0.0.1 that exposes the live windows to overlays and bots
+ */
+export type LocalApiConfig = {
+	enabled: boolean,
+	port: number,
+	/**
+	 * Required as `Authorization: Bearer <token>` or `?token=<token>` when not empty
+	 */
+	token: string,
 };
 
 export type ModuleOptimizerExportResult = {
@@ -48,8 +158,18 @@ export type PlayerRow = {
 	critValueRate: number | null,
 	luckyRate: number | null,
 	luckyValueRate: number | null,
+	critHits: number | null,
+	critValue: number | null,
+	luckyHits: number | null,
+	luckyValue: number | null,
 	hits: number | null,
 	hitsPerMinute: number | null,
+	rollingValuePerSec: number | null,
+	activeTimeMs: number | null,
+	uptimePct: number | null,
+	activeValuePerSec: number | null,
+	critMultiplier: number | null,
+	luckyMultiplier: number | null,
 };
 
 export type PlayersWindow = {
@@ -58,6 +178,61 @@ export type PlayersWindow = {
 	topValue: number | null,
 };
 
+export type QueryResult = { Players: PlayersWindow } | { Skills: SkillsWindow };
+
+export type QueryStatKind = "Dmg" | "Heal";
+
+export type QueryTargets = "All" | "Bosses" | { Filter: number } | { MonsterIds: number[] };
+
+export type ResetSnapshotSummary = {
+	index: number | null,
+	timeFightStartMs: number | null,
+	elapsedMs: number | null,
+	totalDmg: number | null,
+};
+
+export type SavedEncounter = {
+	id: string,
+	name: string,
+	endReason: EncounterEndReason | null,
+	timeFightStartMs: number | null,
+	timeLastCombatPacketMs: number | null,
+	headerInfo: HeaderInfo,
+	dpsPlayers: PlayersWindow,
+	dpsBossOnlyPlayers: PlayersWindow,
+	healPlayers: PlayersWindow,
+	dpsSkills: { [key in string]: SkillsWindow },
+	dpsBossOnlySkills: { [key in string]: SkillsWindow },
+	healSkills: { [key in string]: SkillsWindow },
+	lineId: number | null,
+	levelMapId: number | null,
+	entities: SavedEntity[],
+};
+
+export type SavedEncounterSummary = {
+	id: string,
+	name: string,
+	endReason: EncounterEndReason | null,
+	timeFightStartMs: number | null,
+	elapsedMs: number | null,
+	totalDmg: number | null,
+	playerCount: number | null,
+};
+
+/**
+ * Every entity that dealt, healed or took something in a saved encounter, most damage taken first
+ */
+export type SavedEntity = {
+	uid: number | null,
+	entityType: string,
+	name: string | null,
+	monsterId: number | null,
+	isBoss: boolean,
+	maxHp: number | null,
+	dmgTaken: number | null,
+	deaths: number,
+};
+
 export type SkillRow = {
 	uid: number | null,
 	name: string,
@@ -68,8 +243,18 @@ export type SkillRow = {
 	critValueRate: number | null,
 	luckyRate: number | null,
 	luckyValueRate: number | null,
+	critHits: number | null,
+	critValue: number | null,
+	luckyHits: number | null,
+	luckyValue: number | null,
 	hits: number | null,
 	hitsPerMinute: number | null,
+	critMultiplier: number | null,
+	luckyMultiplier: number | null,
+	normalHitDist: HitDistributionRow,
+	critHitDist: HitDistributionRow,
+	luckyHitDist: HitDistributionRow,
+	critLuckyHitDist: HitDistributionRow,
 };
 
 export type SkillsWindow = {
@@ -79,6 +264,43 @@ export type SkillsWindow = {
 	topValue: number | null,
 };
 
+/**
+ * User defined set of targets to get separate damage stats for, e.g. when the real target
+ * isn't in the boss list. Only monsters can match.
+ */
+export type TargetFilter = {
+	id: number,
+	name: string,
+	includeMonsterIds: number[],
+	excludeMonsterIds: number[],
+	minMaxHp: number | null,
+};
+
+export type TimelineSeries = {
+	uid: number | null,
+	name: string,
+	className: string,
+	classSpecName: string,
+	valuesPerSec: (number | null)[],
+};
+
+export type TimelineWindow = {
+	bucketMs: number | null,
+	elapsedMs: number | null,
+	series: TimelineSeries[],
+};
+
+/**
+ * Opt-in sink that posts a summary of every finished encounter to a user defined url
+ */
+export type WebhookConfig = {
+	enabled: boolean,
+	url: string,
+	format: WebhookFormat,
+};
+
+export type WebhookFormat = "JSON" | "Discord";
+
 /* Tauri Specta runtime */
 async function typedError<T, E>(result: Promise<T>): Promise<{ status: "ok"; data: T } | { status: "error"; error: E }> {
     try {
@@ -89,3 +311,19 @@ async function typedError<T, E>(result: Promise<T>): Promise<{ status: "ok"; dat
     }
 }
 
+function makeEvents<T extends Record<string, any>>(mappings: Record<keyof T, string>) {
+    return Object.fromEntries(
+        Object.entries(mappings).map(([event, name]) => [
+            event,
+            {
+                listen: (cb: __TAURI_EVENT.EventCallback<any>) => __TAURI_EVENT.listen(name, cb),
+                once: (cb: __TAURI_EVENT.EventCallback<any>) => __TAURI_EVENT.once(name, cb),
+            },
+        ]),
+    ) as {
+        [K in keyof T]: {
+            listen: (cb: __TAURI_EVENT.EventCallback<T[K]>) => ReturnType<typeof __TAURI_EVENT.listen<T[K]>>;
+            once: (cb: __TAURI_EVENT.EventCallback<T[K]>) => ReturnType<typeof __TAURI_EVENT.once<T[K]>>;
+        };
+    };
+}
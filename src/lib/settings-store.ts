@@ -1,4 +1,5 @@
 import { RuneStore } from '@tauri-store/svelte';
+import type { FightStartTrigger, TargetFilter } from '$lib/bindings';
 
 export const DEFAULT_STATS = {
 	totalValue: true,
@@ -19,6 +20,10 @@ const DEFAULT_SETTINGS = {
 		showYourAbilityScore: true,
 		showOthersAbilityScore: true,
 		resetElapsed: 60,
+		rollingWindowSecs: 10,
+		fightStartTrigger: 'Any' as FightStartTrigger,
+		targetFilters: [] as TargetFilter[],
+		liveUpdateIntervalMs: 200,
 		shortenAbilityScore: false,
 		bossOnly: false,
 		autostart: true
@@ -45,7 +50,13 @@ const DEFAULT_SETTINGS = {
 		testingMode: false
 	},
 	integration: {
-		bptimer: true
+		bptimer: true,
+		localApi: false,
+		localApiPort: 7878,
+		localApiToken: '',
+		webhook: false,
+		webhookUrl: '',
+		webhookFormat: 'JSON' // 'JSON' | 'Discord'
 	}
 };
 